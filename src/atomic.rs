@@ -0,0 +1,73 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// A file that is written under a temporary name in the destination directory
+/// and only renamed over the destination on `commit`. Readers never see a
+/// truncated or half-written file; dropping it uncommitted removes the temp file.
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: Option<File>,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<AtomicFile> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name"))?;
+        let tmp_name = format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id());
+        let tmp_path = path.with_file_name(tmp_name);
+        let file = File::create(&tmp_path)?;
+        Ok(AtomicFile { path: path.to_path_buf(), tmp_path, file: Some(file) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush and fsync the temp file, then rename it over the destination.
+    pub fn commit(mut self) -> io::Result<()> {
+        let mut file = self.file.take().expect("AtomicFile committed twice");
+        file.flush()?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.tmp_path, &self.path)?;
+        sync_parent_dir(&self.path);
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().expect("write after commit").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().expect("flush after commit").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+// Persist the rename itself; best effort, as not every platform can open directories
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) {}
@@ -0,0 +1,129 @@
+use futures::{stream, StreamExt};
+use reqwest::{header, Client, Response, StatusCode};
+use std::fmt;
+
+use crate::feed::Episode;
+use crate::retention::format_size;
+
+/// Something wrong with an episode's media URL.
+pub enum Problem {
+    /// 404 Not Found or 410 Gone
+    Dead(StatusCode),
+    /// Any other error status
+    Status(StatusCode),
+    /// The request did not get an answer at all
+    Unreachable(String),
+    /// The server answers with something that is not audio or video, such as an HTML page
+    WrongType(String),
+    /// The file is a different size than the feed's enclosure length says
+    SizeMismatch { advertised: u64, actual: u64 },
+}
+
+impl Problem {
+    /// Whether a player would fail on this URL; size mismatches are only worth a warning.
+    pub fn is_broken(&self) -> bool {
+        !matches!(self, Problem::SizeMismatch { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Dead(status) | Problem::Status(status) => write!(f, "HTTP {}", status.as_u16()),
+            Problem::Unreachable(reason) => write!(f, "unreachable: {}", reason),
+            Problem::WrongType(content_type) => write!(f, "served as {}", content_type),
+            Problem::SizeMismatch { advertised, actual } => write!(
+                f,
+                "{} instead of the advertised {}",
+                size_label(*actual),
+                size_label(*advertised)
+            ),
+        }
+    }
+}
+
+fn size_label(bytes: u64) -> String {
+    if bytes >= 1_000_000 { format_size(bytes) } else { format!("{} bytes", bytes) }
+}
+
+// Enclosure lengths are often rounded or stale; only a difference beyond this fraction counts
+const SIZE_TOLERANCE: f64 = 0.1;
+
+/// Check the media URL of every episode, `jobs` at a time, in the order given.
+pub async fn check_links(client: &Client, episodes: &[Episode], jobs: usize) -> Vec<Vec<Problem>> {
+    stream::iter(episodes)
+        .map(|episode| check(client, episode))
+        .buffered(jobs)
+        .collect()
+        .await
+}
+
+async fn check(client: &Client, episode: &Episode) -> Vec<Problem> {
+    let url = &episode.media_url;
+    let mut response = match client.head(url).send().await {
+        Ok(response) => response,
+        Err(e) => return vec![Problem::Unreachable(e.without_url().to_string())],
+    };
+    // Some hosts refuse HEAD or leave out the length; the first byte of a ranged GET tells the same
+    let refused = matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::FORBIDDEN | StatusCode::NOT_IMPLEMENTED
+    );
+    if refused || (response.status().is_success() && full_size(&response).is_none()) {
+        response = match client.get(url).header(header::RANGE, "bytes=0-0").send().await {
+            Ok(response) => response,
+            Err(e) => return vec![Problem::Unreachable(e.without_url().to_string())],
+        };
+    }
+
+    let status = response.status();
+    if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return vec![Problem::Dead(status)];
+    }
+    if !status.is_success() {
+        return vec![Problem::Status(status)];
+    }
+
+    let mut problems = Vec::new();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or_default().trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty());
+    if let Some(content_type) = content_type.filter(|t| !is_media_type(t)) {
+        problems.push(Problem::WrongType(content_type));
+    }
+    if let (Some(advertised), Some(actual)) = (episode.enclosure_length.filter(|&len| len > 1), full_size(&response)) {
+        if (actual as f64 - advertised as f64).abs() > advertised as f64 * SIZE_TOLERANCE {
+            problems.push(Problem::SizeMismatch { advertised, actual });
+        }
+    }
+    problems
+}
+
+// The size of the whole file: the total of a Content-Range, or the Content-Length of a full answer
+fn full_size(response: &Response) -> Option<u64> {
+    let headers = response.headers();
+    if response.status() == StatusCode::PARTIAL_CONTENT {
+        return headers
+            .get(header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|total| total.trim().parse().ok());
+    }
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&len| len > 0)
+}
+
+fn is_media_type(content_type: &str) -> bool {
+    content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        || matches!(
+            content_type,
+            "application/octet-stream" | "binary/octet-stream" | "application/ogg" | "application/mp4"
+        )
+}
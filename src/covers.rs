@@ -0,0 +1,96 @@
+use futures::{stream, StreamExt};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage};
+use reqwest::Client;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::atomic::AtomicFile;
+use crate::error::{Error, Result};
+use crate::naming::short_id;
+
+// Artwork is rarely over a few megabytes; anything far larger is not read into memory
+const MAX_COVER_BYTES: usize = 20 * 1024 * 1024;
+
+/// Download, shrink and cache every artwork URL in `dir`, returning the absolute cached
+/// path of each URL that worked. A cover already in the cache is not fetched again; the
+/// file name carries the size, so a different `size` builds a fresh cache entry.
+pub async fn cache_covers(client: &Client, urls: Vec<String>, dir: &Path, size: u32) -> Result<HashMap<String, PathBuf>> {
+    std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+    let dir = &dir.canonicalize().map_err(Error::io(dir))?;
+    let cached = stream::iter(urls)
+        .map(|url| async move {
+            let path = dir.join(format!("{}-{}.jpg", short_id(&url), size));
+            if path.exists() {
+                return Some((url, path));
+            }
+            match cache_cover(client, &url, &path, size).await {
+                Ok(()) => Some((url, path)),
+                Err(reason) => {
+                    eprintln!("Warning: cannot cache cover {}: {}", url, reason);
+                    None
+                }
+            }
+        })
+        .buffer_unordered(8)
+        .filter_map(|cached| async move { cached })
+        .collect()
+        .await;
+    Ok(cached)
+}
+
+async fn cache_cover(client: &Client, url: &str, path: &Path, size: u32) -> std::result::Result<(), String> {
+    let mut resp = client.get(url).send().await.map_err(|e| e.without_url().to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("HTTP {}", resp.status()));
+    }
+    let too_large = || format!("larger than {} MB", MAX_COVER_BYTES / 1024 / 1024);
+    if resp.content_length().is_some_and(|len| len > MAX_COVER_BYTES as u64) {
+        return Err(too_large());
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = resp.chunk().await.map_err(|e| e.without_url().to_string())? {
+        if data.len() + chunk.len() > MAX_COVER_BYTES {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+        write_jpeg(&shrink(image, size), &path).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Fit within size x size, never enlarging; JPEG has no alpha, so flatten to RGB
+fn shrink(image: DynamicImage, size: u32) -> DynamicImage {
+    let image = if image.width() > size || image.height() > size {
+        image.resize(size, size, FilterType::Lanczos3)
+    } else {
+        image
+    };
+    DynamicImage::ImageRgb8(image.to_rgb8())
+}
+
+fn write_jpeg(image: &DynamicImage, path: &Path) -> std::io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    image
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut file, 85))
+        .map_err(std::io::Error::other)?;
+    file.flush()?;
+    file.commit()
+}
+
+/// `path` as seen from the directory `base`, both absolute, e.g. `../covers/x.jpg`.
+pub fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&path[common..]);
+    relative
+}
@@ -0,0 +1,389 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::fmt::Write;
+
+// UTC offsets in minutes for the zone abbreviations feeds use in practice.
+// Ambiguous ones (IST, CST) follow the most common podcast-feed meaning.
+const ZONES: &[(&str, i32)] = &[
+    ("UT", 0), ("UTC", 0), ("GMT", 0), ("Z", 0), ("WET", 0), ("WEST", 60),
+    ("BST", 60), ("IST", 330), ("CET", 60), ("CEST", 120), ("MET", 60), ("MEST", 120), ("MEZ", 60), ("MESZ", 120),
+    ("EET", 120), ("EEST", 180), ("MSK", 180),
+    ("EST", -300), ("EDT", -240), ("CST", -360), ("CDT", -300),
+    ("MST", -420), ("MDT", -360), ("PST", -480), ("PDT", -420),
+    ("AKST", -540), ("AKDT", -480), ("HST", -600), ("AST", -240), ("ADT", -180),
+    ("NST", -210), ("NDT", -150), ("BRT", -180), ("ART", -180),
+    ("JST", 540), ("KST", 540), ("HKT", 480), ("SGT", 480), ("AWST", 480),
+    ("ACST", 570), ("ACDT", 630), ("AEST", 600), ("AEDT", 660),
+    ("NZST", 720), ("NZDT", 780), ("SAST", 120), ("WAT", 60), ("EAT", 180),
+];
+
+// Month names and abbreviations in the languages feeds are commonly written in
+const MONTHS: &[(&str, u32)] = &[
+    ("jan", 1), ("ene", 1), ("gen", 1), ("janv", 1), ("jän", 1),
+    ("feb", 2), ("fév", 2), ("fev", 2), ("févr", 2), ("fevr", 2),
+    ("mar", 3), ("mär", 3), ("maerz", 3), ("mars", 3), ("mrt", 3),
+    ("apr", 4), ("avr", 4), ("abr", 4),
+    ("may", 5), ("mai", 5), ("mag", 5), ("mei", 5), ("mayo", 5),
+    ("jun", 6), ("juin", 6), ("giu", 6),
+    ("jul", 7), ("juil", 7), ("lug", 7),
+    ("aug", 8), ("août", 8), ("aout", 8), ("ago", 8),
+    ("sep", 9), ("sept", 9), ("set", 9),
+    ("oct", 10), ("okt", 10), ("ott", 10), ("out", 10),
+    ("nov", 11),
+    ("dec", 12), ("déc", 12), ("dez", 12), ("dic", 12),
+];
+
+// Leading day names, in English, German, French, Spanish, Italian, Dutch, Portuguese
+// and the Scandinavian languages: in full and abbreviated. Only whole words count,
+// so a zone such as SAST is not taken for "Sa"
+const DAY_NAMES: &[&str] = &[
+    "monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday",
+    "mon", "tue", "tues", "wed", "thu", "thur", "thurs", "fri", "sat", "sun",
+    "montag", "dienstag", "mittwoch", "donnerstag", "freitag", "samstag", "sonnabend", "sonntag",
+    "mo", "di", "mi", "do", "fr", "sa", "so",
+    "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+    "lun", "mar", "mer", "jeu", "ven", "sam", "dim",
+    "lunes", "martes", "miércoles", "miercoles", "jueves", "viernes", "sábado", "sabado", "domingo",
+    "mié", "mie", "jue", "vie", "sáb", "sab", "dom",
+    "lunedì", "lunedi", "martedì", "martedi", "mercoledì", "mercoledi", "giovedì", "giovedi",
+    "venerdì", "venerdi", "sabato", "domenica", "gio",
+    "maandag", "dinsdag", "woensdag", "donderdag", "vrijdag", "zaterdag", "zondag",
+    "ma", "wo", "vr", "za", "zo",
+    "segunda", "terça", "terca", "quarta", "quinta", "sexta",
+    "segunda-feira", "terça-feira", "terca-feira", "quarta-feira", "quinta-feira", "sexta-feira",
+    "seg", "ter", "qua", "qui", "sex",
+    "mandag", "tirsdag", "onsdag", "torsdag", "fredag", "lørdag", "lordag", "søndag", "sondag",
+    "måndag", "tisdag", "lördag", "söndag",
+    "man", "tir", "ons", "tor", "fre", "lør", "lor", "søn", "son", "mån", "tis", "lör", "sön",
+];
+
+/// Format a date with a strftime spec given on the command line; `None` when the spec
+/// is invalid, where `format(..).to_string()` would panic.
+pub fn format_date(date: DateTime<Utc>, spec: &str) -> Option<String> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format(spec)).ok()?;
+    Some(formatted)
+}
+
+/// Parse a feed publication date as leniently as feeds write them.
+///
+/// Beyond RFC 2822 and RFC 3339 this accepts zone abbreviations (`EST`, `CEST`),
+/// `GMT+2`-style offsets, two-digit years, missing seconds, day and month names in
+/// several languages, and bare dates, which are taken as midnight UTC.
+pub fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(s).or_else(|_| DateTime::parse_from_rfc3339(s)) {
+        return Some(date.with_timezone(&Utc));
+    }
+    parse_lenient(s)
+}
+
+#[derive(Default)]
+struct Parts {
+    year: Option<i32>,
+    month: Option<u32>,
+    day: Option<u32>,
+    time: Option<NaiveTime>,
+    offset_minutes: Option<i32>,
+}
+
+fn parse_lenient(s: &str) -> Option<DateTime<Utc>> {
+    let cleaned: String = s
+        .chars()
+        .map(|c| if matches!(c, ',' | '(' | ')') { ' ' } else { c })
+        .collect();
+    let mut parts = Parts::default();
+    let mut pm: Option<bool> = None;
+
+    let tokens: Vec<String> = cleaned.split_whitespace().flat_map(split_iso).collect();
+    let month_words = tokens
+        .iter()
+        .filter(|t| month_number(t.to_lowercase().trim_end_matches('.')).is_some())
+        .count();
+    for (i, token) in tokens.iter().enumerate() {
+        let lower = token.to_lowercase();
+        let word = lower.trim_end_matches('.');
+
+        if word == "am" || word == "a.m" {
+            pm = Some(false);
+        } else if word == "pm" || word == "p.m" {
+            pm = Some(true);
+        } else if let Some(offset) = parse_offset(token) {
+            if parts.offset_minutes.is_none() {
+                parts.offset_minutes = Some(offset);
+            }
+        } else if token.contains(':') && parts.time.is_none() {
+            let (time, offset) = parse_time(token)?;
+            parts.time = Some(time);
+            if offset.is_some() {
+                parts.offset_minutes = offset;
+            }
+        } else if let Some((year, month, day)) = parse_numeric_date(token) {
+            parts.year = Some(year);
+            parts.month = Some(month);
+            parts.day = Some(day);
+        } else if word.chars().all(|c| c.is_ascii_digit()) && !word.is_empty() {
+            let n: u32 = word.parse().ok()?;
+            if word.len() == 4 && parts.year.is_none() {
+                parts.year = Some(n as i32);
+            } else if parts.day.is_none() && (1..=31).contains(&n) && !(parts.year.is_some() && parts.month.is_none()) {
+                parts.day = Some(n);
+            } else if parts.year.is_none() && word.len() == 2 {
+                parts.year = Some(two_digit_year(n));
+            } else if parts.day.is_none() && (1..=31).contains(&n) {
+                parts.day = Some(n);
+            } else {
+                return None;
+            }
+        } else if let Some(month) = month_number(word).filter(|_| parts.month.is_none()) {
+            // "Mar" is both March and a day name (mardi, martes); a leading one is the day
+            // when another month name follows
+            if i == 0 && month_words > 1 && is_day_name(word) {
+                continue;
+            }
+            parts.month = Some(month);
+        } else if is_day_name(word) || matches!(word, "de" | "del" | "der" | "den" | "at" | "um" | "à") {
+            // Day names and filler words ("1 de septiembre") carry nothing we need
+            continue;
+        } else {
+            return None;
+        }
+    }
+
+    let date = NaiveDate::from_ymd_opt(parts.year?, parts.month?, parts.day?)?;
+    let mut time = parts.time.unwrap_or(NaiveTime::MIN);
+    if let Some(pm) = pm {
+        time = apply_meridiem(time, pm)?;
+    }
+    let offset = FixedOffset::east_opt(parts.offset_minutes.unwrap_or(0) * 60)?;
+    let local = offset.from_local_datetime(&date.and_time(time)).single()?;
+    Some(local.with_timezone(&Utc))
+}
+
+// Split "2024-05-01T10:00:00+02:00" into its date and time parts
+fn split_iso(token: &str) -> Vec<String> {
+    match token.split_once(['T', 't']) {
+        Some((date, time)) if parse_numeric_date(date).is_some() && !time.is_empty() => {
+            vec![date.to_string(), time.to_string()]
+        }
+        _ => vec![token.to_string()],
+    }
+}
+
+// YYYY-MM-DD, YYYY/MM/DD, or the European DD.MM.YYYY
+fn parse_numeric_date(token: &str) -> Option<(i32, u32, u32)> {
+    let fields: Vec<&str> = token.split(['-', '/', '.']).collect();
+    if fields.len() != 3 || fields.iter().any(|f| f.is_empty() || !f.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    let numbers: Vec<u32> = fields.iter().map(|f| f.parse().ok()).collect::<Option<_>>()?;
+    if fields[0].len() == 4 {
+        Some((numbers[0] as i32, numbers[1], numbers[2]))
+    } else if fields[2].len() == 4 && token.contains('.') {
+        Some((numbers[2] as i32, numbers[1], numbers[0]))
+    } else {
+        None
+    }
+}
+
+// HH:MM or HH:MM:SS(.fff), with an optional zone glued on ("10:00Z", "10:00:00+0200")
+fn parse_time(token: &str) -> Option<(NaiveTime, Option<i32>)> {
+    let split = token
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| c == '+' || c == '-' || c.is_ascii_alphabetic())
+        .map(|(i, _)| i)
+        .unwrap_or(token.len());
+    let (clock, zone) = token.split_at(split);
+    let offset = if zone.is_empty() { None } else { Some(parse_offset(zone)?) };
+
+    let clock = clock.split('.').next()?;
+    let fields: Vec<u32> = clock.split(':').map(|f| f.parse().ok()).collect::<Option<_>>()?;
+    let time = match fields[..] {
+        [h, m] => NaiveTime::from_hms_opt(h, m, 0)?,
+        [h, m, s] => NaiveTime::from_hms_opt(h, m, s.min(59))?,
+        _ => return None,
+    };
+    Some((time, offset))
+}
+
+// Zone abbreviation, numeric offset ("+0200", "-05:00", "+2"), or both ("GMT+2", "UTC-05:00")
+fn parse_offset(token: &str) -> Option<i32> {
+    let upper = token.to_uppercase();
+    let sign_at = upper.find(['+', '-']);
+    let (name, numeric) = match sign_at {
+        Some(i) => upper.split_at(i),
+        None => (upper.as_str(), ""),
+    };
+    let base = if name.is_empty() {
+        0
+    } else {
+        ZONES.iter().find(|(zone, _)| *zone == name).map(|&(_, offset)| offset)?
+    };
+    if numeric.is_empty() {
+        return Some(base);
+    }
+    // Without a zone name, only "+HH", "+HHMM" and "+HH:MM" count as offsets
+    if name.is_empty() && numeric.len() < 3 {
+        return None;
+    }
+    let sign = if numeric.starts_with('-') { -1 } else { 1 };
+    let digits: String = numeric[1..].chars().filter(|c| *c != ':').collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes): (i32, i32) = match digits.len() {
+        1 | 2 => (digits.parse().ok()?, 0),
+        3 => (digits[..1].parse().ok()?, digits[1..].parse().ok()?),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    Some(base + sign * (hours * 60 + minutes))
+}
+
+fn month_number(word: &str) -> Option<u32> {
+    if word.chars().count() < 3 {
+        return None;
+    }
+    MONTHS.iter()
+        .find(|(name, _)| word == *name)
+        .or_else(|| MONTHS.iter().find(|(name, _)| name.chars().count() == 3 && word.starts_with(name)))
+        .map(|&(_, month)| month)
+}
+
+fn is_day_name(word: &str) -> bool {
+    DAY_NAMES.contains(&word)
+}
+
+// RFC 2822 reading: 00-49 is 20xx, 50-99 is 19xx
+fn two_digit_year(n: u32) -> i32 {
+    if n < 50 { 2000 + n as i32 } else { 1900 + n as i32 }
+}
+
+fn apply_meridiem(time: NaiveTime, pm: bool) -> Option<NaiveTime> {
+    use chrono::Timelike;
+    let hour = match (time.hour(), pm) {
+        (12, false) => 0,
+        (h, false) => h,
+        (12, true) => 12,
+        (h, true) if h < 12 => h + 12,
+        _ => return None,
+    };
+    time.with_hour(hour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, parse_date};
+
+    // Publication dates seen in real podcast feeds, with the instant they stand for
+    const CORPUS: &[(&str, &str)] = &[
+        ("Tue, 01 Sep 2026 10:00:00 +0000", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 GMT", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 EST", "2026-09-01T15:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 EDT", "2026-09-01T14:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 PDT", "2026-09-01T17:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 CEST", "2026-09-01T08:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 AEST", "2026-09-01T00:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 UTC", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 Z", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00 +0200", "2026-09-01T08:00:00Z"),
+        ("Tue, 1 Sep 2026 10:00 GMT", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 26 10:00:00 +0000", "2026-09-01T10:00:00Z"),
+        ("01 Sep 26 10:00 EST", "2026-09-01T15:00:00Z"),
+        ("Wed, 02 Sep 2026 10:00:00 +0000", "2026-09-02T10:00:00Z"),
+        // Wrong weekday, as written by hand-rolled feed generators
+        ("Mon, 01 Sep 2026 10:00:00 +0000", "2026-09-01T10:00:00Z"),
+        ("Tuesday, 01 September 2026 10:00:00 GMT", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sept 2026 10:00:00 GMT", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 GMT+2", "2026-09-01T08:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 GMT+02:00", "2026-09-01T08:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 UTC-5", "2026-09-01T15:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 +02:00", "2026-09-01T08:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 -0430", "2026-09-01T14:30:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 (PST)", "2026-09-01T18:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00", "2026-09-01T10:00:00Z"),
+        ("Tue, 01 Sep 2026", "2026-09-01T00:00:00Z"),
+        ("01 Sep 2026", "2026-09-01T00:00:00Z"),
+        ("Sep 1, 2026", "2026-09-01T00:00:00Z"),
+        ("September 1, 2026 10:00 am", "2026-09-01T10:00:00Z"),
+        ("Sep 1, 2026 3:30 PM EST", "2026-09-01T20:30:00Z"),
+        ("Sep 1, 2026 12:15 AM", "2026-09-01T00:15:00Z"),
+        ("2026-09-01", "2026-09-01T00:00:00Z"),
+        ("2026/09/01", "2026-09-01T00:00:00Z"),
+        ("01.09.2026", "2026-09-01T00:00:00Z"),
+        ("2026-09-01T10:00:00Z", "2026-09-01T10:00:00Z"),
+        ("2026-09-01T10:00:00+02:00", "2026-09-01T08:00:00Z"),
+        ("2026-09-01T10:00:00.123Z", "2026-09-01T10:00:00Z"),
+        ("2026-09-01T10:00Z", "2026-09-01T10:00:00Z"),
+        ("2026-09-01T10:00:00+0200", "2026-09-01T08:00:00Z"),
+        ("2026-09-01 10:00:00", "2026-09-01T10:00:00Z"),
+        ("2026-09-01 10:00:00 +0000", "2026-09-01T10:00:00Z"),
+        ("2026-09-01T10:00:00", "2026-09-01T10:00:00Z"),
+        ("Di, 01 Sep 2026 10:00:00 +0200", "2026-09-01T08:00:00Z"),
+        ("Dienstag, 01 Sep 2026 10:00:00 +0200", "2026-09-01T08:00:00Z"),
+        ("Mi, 14 Okt 2026 10:00:00 +0200", "2026-10-14T08:00:00Z"),
+        ("Mar, 01 Sep 2026 10:00:00 +0200", "2026-09-01T08:00:00Z"),
+        ("mar., 01 sept. 2026 10:00:00 +0200", "2026-09-01T08:00:00Z"),
+        ("jeu., 03 déc. 2026 10:00:00 +0100", "2026-12-03T09:00:00Z"),
+        ("Mié, 02 Dic 2026 10:00:00 +0100", "2026-12-02T09:00:00Z"),
+        ("Do, 31 Dez 2026 23:59:59 +0100", "2026-12-31T22:59:59Z"),
+        ("lør, 05 mai 2026 08:00:00 +0200", "2026-05-05T06:00:00Z"),
+        ("  Tue, 01 Sep 2026 10:00:00 GMT  ", "2026-09-01T10:00:00Z"),
+        ("Tue,01 Sep 2026 10:00:00 GMT", "2026-09-01T10:00:00Z"),
+        ("Thu, 31 Dec 98 23:00:00 GMT", "1998-12-31T23:00:00Z"),
+        ("Sat, 29 Feb 2020 12:00:00 +0000", "2020-02-29T12:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 +0000 (UTC)", "2026-09-01T10:00:00Z"),
+        ("1 de septiembre de 2026", "2026-09-01T00:00:00Z"),
+        ("Tue, 01 Sep 2026 10:00:00 SAST", "2026-09-01T08:00:00Z"),
+        ("Donnerstag, 03 Dez 2026 10:00:00 +0100", "2026-12-03T09:00:00Z"),
+        ("mercredi 02 déc. 2026 10:00 +0100", "2026-12-02T09:00:00Z"),
+        ("Terça-feira, 01 Set 2026 10:00:00 -0300", "2026-09-01T13:00:00Z"),
+    ];
+
+    const UNPARSEABLE: &[&str] = &[
+        "",
+        "unknown",
+        "TBD",
+        "Sep 2026",
+        "31 Feb 2026",
+        "Tue, 01 Xyz 2026 10:00:00 GMT",
+        "Tue, 01 Sep 2026 25:00:00 GMT",
+        "09/01/2026",
+        // Unknown zones must not pass for a day name and leave the time in UTC
+        "Tue, 01 Sep 2026 10:00:00 SAMT",
+        "Tue, 01 Sep 2026 10:00:00 MART",
+    ];
+
+    #[test]
+    fn parses_real_world_dates() {
+        let failures: Vec<String> = CORPUS
+            .iter()
+            .filter_map(|(input, expected)| {
+                let parsed = parse_date(input).map(|d| d.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+                (parsed.as_deref() != Some(*expected)).then(|| format!("{:?}: got {:?}, want {}", input, parsed, expected))
+            })
+            .collect();
+        assert!(failures.is_empty(), "misparsed dates:\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for input in UNPARSEABLE {
+            assert_eq!(parse_date(input), None, "{:?} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn formats_with_user_specs() {
+        let date = parse_date("2026-09-01T10:00:00Z").unwrap();
+        assert_eq!(format_date(date, "%Y%m%d").as_deref(), Some("20260901"));
+        assert_eq!(format_date(date, "%Q"), None);
+        assert_eq!(format_date(date, "%"), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::feed::Episode;
+use crate::trackers::TrackerRules;
+
+/// Why two episodes were taken for the same one.
+#[derive(Clone, Copy)]
+pub enum Match {
+    Guid,
+    MediaUrl,
+    TitleAndDate,
+}
+
+impl Match {
+    fn describe(self) -> &'static str {
+        match self {
+            Match::Guid => "same guid",
+            Match::MediaUrl => "same media URL",
+            Match::TitleAndDate => "similar title and date",
+        }
+    }
+}
+
+/// An episode dropped because another feed carries it too.
+pub struct Duplicate {
+    pub title: String,
+    pub media_url: String,
+    pub dropped_feed: String,
+    pub kept_feed: String,
+    pub matched: Match,
+}
+
+impl Duplicate {
+    pub fn report(&self) -> String {
+        format!(
+            "'{}' in {} duplicates {} ({}); kept the copy from {}",
+            self.title,
+            self.dropped_feed,
+            self.kept_feed,
+            self.matched.describe(),
+            self.kept_feed
+        )
+    }
+}
+
+// Fuzzy matches need titles this alike (shared words over all words) ...
+const TITLE_SIMILARITY: f64 = 0.8;
+// ... published at most this many days apart
+const DATE_SLACK_DAYS: i64 = 2;
+
+/// Drop episodes that another copy already covers. Copies are matched on guid, on the
+/// media URL with tracking redirects and query strings stripped, and on a similar title
+/// published within a couple of days in another feed. The copy from the feed with the
+/// lowest `feed_rank` (its position in the OPML file) is kept; order is otherwise preserved.
+pub fn dedup(episodes: &mut Vec<Episode>, feed_rank: &HashMap<String, usize>, trackers: &TrackerRules) -> Vec<Duplicate> {
+    let rank = |e: &Episode| feed_rank.get(&e.feed_url).copied().unwrap_or(usize::MAX);
+    let mut order: Vec<usize> = (0..episodes.len()).collect();
+    order.sort_by_key(|&i| rank(&episodes[i]));
+
+    let mut by_guid: HashMap<&str, usize> = HashMap::new();
+    let mut by_url: HashMap<String, usize> = HashMap::new();
+    let mut by_day: HashMap<i64, Vec<usize>> = HashMap::new();
+    let mut dropped: HashSet<usize> = HashSet::new();
+    let mut duplicates = Vec::new();
+
+    for i in order {
+        let episode = &episodes[i];
+        let url = normalize_url(&episode.media_url, trackers);
+        let words = title_words(&episode.title);
+        let day = episode.pub_date.map(|d| d.timestamp().div_euclid(86_400));
+
+        let found = distinctive_guid(&episode.guid)
+            .and_then(|guid| by_guid.get(guid))
+            .map(|&kept| (kept, Match::Guid))
+            .or_else(|| by_url.get(&url).map(|&kept| (kept, Match::MediaUrl)))
+            .or_else(|| {
+                let day = day?;
+                (day - DATE_SLACK_DAYS..=day + DATE_SLACK_DAYS)
+                    .flat_map(|d| by_day.get(&d).into_iter().flatten())
+                    .find(|&&kept| {
+                        episodes[kept].feed_url != episode.feed_url
+                            && similarity(&words, &title_words(&episodes[kept].title)) >= TITLE_SIMILARITY
+                    })
+                    .map(|&kept| (kept, Match::TitleAndDate))
+            });
+
+        match found {
+            Some((kept, matched)) => {
+                dropped.insert(i);
+                duplicates.push(Duplicate {
+                    title: episode.title.clone(),
+                    media_url: episode.media_url.clone(),
+                    dropped_feed: episode.feed_name.clone(),
+                    kept_feed: episodes[kept].feed_name.clone(),
+                    matched,
+                });
+            }
+            None => {
+                if let Some(guid) = distinctive_guid(&episode.guid) {
+                    by_guid.insert(guid, i);
+                }
+                by_url.insert(url, i);
+                if let Some(day) = day {
+                    by_day.entry(day).or_default().push(i);
+                }
+            }
+        }
+    }
+
+    let mut index = 0;
+    episodes.retain(|_| {
+        index += 1;
+        !dropped.contains(&(index - 1))
+    });
+    duplicates
+}
+
+// Short or numeric guids ("42", "ep-7") repeat across unrelated feeds
+fn distinctive_guid(guid: &str) -> Option<&str> {
+    let guid = guid.trim();
+    (guid.len() >= 12 && !guid.chars().all(|c| c.is_ascii_digit())).then_some(guid)
+}
+
+// The media URL without scheme, query, fragment and tracking redirects, for comparing copies
+fn normalize_url(url: &str, trackers: &TrackerRules) -> String {
+    let url = trackers.strip(url).unwrap_or_else(|| url.trim().to_string());
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.trim_end_matches('/').to_ascii_lowercase()
+}
+
+fn title_words(title: &str) -> HashSet<String> {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / a.union(b).count() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn episode(feed: &str, guid: &str, title: &str, day: u32, url: &str) -> Episode {
+        Episode {
+            feed_name: feed.to_string(),
+            feed_url: format!("https://example.com/{}.xml", feed),
+            group: None,
+            guid: guid.to_string(),
+            title: title.to_string(),
+            pub_date: Some(Utc.with_ymd_and_hms(2026, 9, day, 10, 0, 0).unwrap()),
+            media_url: url.to_string(),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: None,
+            duration: None,
+            broken: None,
+            cover: None,
+            sources: Vec::new(),
+        }
+    }
+
+    // Runs dedup with the feeds ranked in the order given and returns the feed and guid of every survivor
+    fn survivors(mut episodes: Vec<Episode>, feeds: &[&str]) -> Vec<String> {
+        let rank = feeds.iter().enumerate().map(|(i, feed)| (format!("https://example.com/{}.xml", feed), i)).collect();
+        dedup(&mut episodes, &rank, &TrackerRules::builtin());
+        episodes.iter().map(|e| format!("{}:{}", e.feed_name, e.guid)).collect()
+    }
+
+    #[test]
+    fn matches_distinctive_guids_only() {
+        let episodes = vec![
+            episode("b", "urn:uuid:1234-5678", "Backup copy", 1, "https://b.example/1.mp3"),
+            episode("a", "urn:uuid:1234-5678", "Original", 1, "https://a.example/1.mp3"),
+            // Short and numeric guids repeat across unrelated feeds
+            episode("a", "42", "Answer", 3, "https://a.example/42.mp3"),
+            episode("b", "42", "Question", 3, "https://b.example/42.mp3"),
+            episode("a", "123456789012345", "Long number", 5, "https://a.example/n.mp3"),
+            episode("b", "123456789012345", "Other number", 5, "https://b.example/n.mp3"),
+        ];
+        // The copy of the feed ranked first is kept, wherever it is in the list
+        assert_eq!(
+            survivors(episodes, &["a", "b"]),
+            ["a:urn:uuid:1234-5678", "a:42", "b:42", "a:123456789012345", "b:123456789012345"]
+        );
+    }
+
+    #[test]
+    fn matches_media_urls_behind_trackers_and_queries() {
+        let episodes = vec![
+            episode("a", "a1", "One", 1, "https://cdn.example.com/show/1.mp3?source=rss"),
+            episode("b", "b1", "Uno", 1, "https://dts.podtrac.com/redirect.mp3/cdn.example.com/show/1.mp3"),
+            episode("b", "b2", "Two", 1, "https://cdn.example.com/show/2.mp3"),
+        ];
+        assert_eq!(survivors(episodes, &["a", "b"]), ["a:a1", "b:b2"]);
+    }
+
+    #[test]
+    fn matches_similar_titles_within_two_days() {
+        let episodes = vec![
+            episode("a", "a1", "Episode 12: The Big Interview", 10, "https://a.example/1.mp3"),
+            episode("b", "b1", "The big interview (Episode 12)", 12, "https://b.example/1.mp3"),
+            // Three days after the last copy is too late
+            episode("b", "b2", "Episode 12: The Big Interview", 14, "https://b.example/2.mp3"),
+            // Four of six words shared is not alike enough
+            episode("b", "b3", "Episode 12: The Big Debate", 10, "https://b.example/3.mp3"),
+            // Nor is a copy in the same feed
+            episode("a", "a2", "Episode 12: The Big Interview", 11, "https://a.example/2.mp3"),
+        ];
+        assert_eq!(survivors(episodes, &["a", "b"]), ["a:a1", "b:b2", "b:b3", "a:a2"]);
+    }
+}
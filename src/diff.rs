@@ -0,0 +1,162 @@
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, Write},
+};
+
+use crate::feed::Episode;
+use crate::output::date_label;
+
+/// Changes between two sets of episodes, such as two outputs or two stored runs.
+pub struct Diff {
+    /// Feeds only in the newer set, with their episode count
+    pub appeared: Vec<(String, usize)>,
+    /// Feeds only in the older set, with their episode count
+    pub vanished: Vec<(String, usize)>,
+    /// Episode changes of the feeds in both sets; feeds without changes are left out
+    pub feeds: Vec<FeedChanges>,
+}
+
+pub struct FeedChanges {
+    pub name: String,
+    pub added: Vec<Episode>,
+    pub removed: Vec<Episode>,
+}
+
+impl Diff {
+    pub fn added(&self) -> usize {
+        self.feeds.iter().map(|f| f.added.len()).sum()
+    }
+
+    pub fn removed(&self) -> usize {
+        self.feeds.iter().map(|f| f.removed.len()).sum()
+    }
+}
+
+/// Compare two sets of episodes. Feeds are told apart by URL, or by name for episodes
+/// read from listings, which carry no feed URL; episodes within a feed by guid.
+pub fn diff(old: &[Episode], new: &[Episode]) -> Diff {
+    let old = by_feed(old);
+    let new = by_feed(new);
+
+    let count = |feeds: &BTreeMap<&str, Vec<&Episode>>, other: &BTreeMap<&str, Vec<&Episode>>| {
+        let mut only: Vec<(String, usize)> = feeds
+            .iter()
+            .filter(|(feed, _)| !other.contains_key(*feed))
+            .map(|(_, episodes)| (episodes[0].feed_name.clone(), episodes.len()))
+            .collect();
+        only.sort();
+        only
+    };
+
+    let mut feeds: Vec<FeedChanges> = new
+        .iter()
+        .filter_map(|(feed, new_episodes)| {
+            let old_episodes = old.get(feed)?;
+            let changes = FeedChanges {
+                name: new_episodes[0].feed_name.clone(),
+                added: missing_from(new_episodes, old_episodes),
+                removed: missing_from(old_episodes, new_episodes),
+            };
+            (!changes.added.is_empty() || !changes.removed.is_empty()).then_some(changes)
+        })
+        .collect();
+    feeds.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Diff { appeared: count(&new, &old), vanished: count(&old, &new), feeds }
+}
+
+fn by_feed(episodes: &[Episode]) -> BTreeMap<&str, Vec<&Episode>> {
+    let mut feeds: BTreeMap<&str, Vec<&Episode>> = BTreeMap::new();
+    for episode in episodes {
+        let feed = if episode.feed_url.is_empty() { &episode.feed_name } else { &episode.feed_url };
+        feeds.entry(feed.as_str()).or_default().push(episode);
+    }
+    feeds
+}
+
+// Episodes of `these` missing from `others`, newest first
+fn missing_from(these: &[&Episode], others: &[&Episode]) -> Vec<Episode> {
+    let known: HashSet<&str> = others.iter().map(|e| e.guid.as_str()).collect();
+    let mut missing: Vec<Episode> = these.iter().filter(|e| !known.contains(e.guid.as_str())).map(|e| (*e).clone()).collect();
+    missing.sort_by(|a, b| b.pub_date.cmp(&a.pub_date).then_with(|| a.title.cmp(&b.title)));
+    missing
+}
+
+/// Write the diff as txt, md or json; `from` and `to` name the compared sets.
+pub fn render<W: Write + ?Sized>(out: &mut W, diff: &Diff, from: &str, to: &str, format: &str) -> io::Result<()> {
+    match format {
+        "json" => {
+            let feeds = |feeds: &[(String, usize)]| -> Vec<Value> {
+                feeds.iter().map(|(name, episodes)| json!({ "name": name, "episodes": episodes })).collect()
+            };
+            let episodes = |episodes: &[Episode]| -> Vec<Value> {
+                episodes
+                    .iter()
+                    .map(|e| {
+                        json!({
+                            "title": e.title,
+                            "date": e.pub_date.map(|d| d.to_rfc3339()),
+                            "media_url": e.media_url,
+                            "guid": e.guid,
+                        })
+                    })
+                    .collect()
+            };
+            let report = json!({
+                "from": from,
+                "to": to,
+                "feeds_appeared": feeds(&diff.appeared),
+                "feeds_vanished": feeds(&diff.vanished),
+                "feeds": diff.feeds.iter().map(|f| json!({
+                    "name": f.name,
+                    "added": episodes(&f.added),
+                    "removed": episodes(&f.removed),
+                })).collect::<Vec<Value>>(),
+            });
+            writeln!(out, "{}", serde_json::to_string_pretty(&report).unwrap())
+        }
+        "md" => {
+            writeln!(out, "# Changes from {} to {}", from, to)?;
+            if !diff.appeared.is_empty() || !diff.vanished.is_empty() {
+                writeln!(out)?;
+                writeln!(out, "## Feeds")?;
+                writeln!(out)?;
+                for (name, episodes) in &diff.appeared {
+                    writeln!(out, "- Appeared: **{}** ({} episodes)", name, episodes)?;
+                }
+                for (name, episodes) in &diff.vanished {
+                    writeln!(out, "- Vanished: **{}** ({} episodes)", name, episodes)?;
+                }
+            }
+            for feed in &diff.feeds {
+                writeln!(out)?;
+                writeln!(out, "## {}", feed.name)?;
+                writeln!(out)?;
+                for (sign, episodes) in [("Added", &feed.added), ("Removed", &feed.removed)] {
+                    for episode in episodes {
+                        writeln!(out, "- {}: **{}** [{}] [Listen]({})", sign, episode.title, date_label(episode), episode.media_url)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        _ => {
+            for (name, episodes) in &diff.appeared {
+                writeln!(out, "+ feed {} ({} episodes)", name, episodes)?;
+            }
+            for (name, episodes) in &diff.vanished {
+                writeln!(out, "- feed {} ({} episodes)", name, episodes)?;
+            }
+            for feed in &diff.feeds {
+                writeln!(out, "{}: {} added, {} removed", feed.name, feed.added.len(), feed.removed.len())?;
+                for (sign, episodes) in [('+', &feed.added), ('-', &feed.removed)] {
+                    for episode in episodes {
+                        writeln!(out, "  {} {} [{}] - {}", sign, episode.title, date_label(episode), episode.media_url)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use futures::future::join_all;
+use reqwest::{header, Client, StatusCode};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Semaphore as TokioSemaphore,
+    time::timeout,
+};
+
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+use crate::tags::{write_tags, Cover};
+
+// A transfer that sends nothing for this long fails; its .part file resumes next run
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub enum Outcome {
+    Downloaded(PathBuf),
+    AlreadyPresent(PathBuf),
+}
+
+pub struct DownloadOptions {
+    /// Number of episodes downloaded in parallel
+    pub jobs: usize,
+    /// Write title, album, date, episode number, description and cover into the file
+    pub write_tags: bool,
+}
+
+/// Download each episode to its destination path, at most `jobs` at a time.
+/// Each result is paired with the episode it belongs to, in the order given.
+pub async fn download_episodes(
+    client: &Client,
+    downloads: Vec<(Episode, PathBuf)>,
+    options: &DownloadOptions,
+) -> Vec<(Episode, Result<Outcome>)> {
+    let sem = Arc::new(TokioSemaphore::new(options.jobs.max(1)));
+    let write_tags = options.write_tags;
+    let (episodes, tasks): (Vec<Episode>, Vec<_>) = downloads
+        .into_iter()
+        .map(|(episode, dest)| {
+            let client = client.clone();
+            let sem = Arc::clone(&sem);
+            let task_episode = episode.clone();
+            let task = tokio::spawn(async move {
+                let _permit = sem.acquire().await.unwrap();
+                let result = download_episode(&client, &task_episode, &dest, write_tags).await;
+                (task_episode, result)
+            });
+            (episode, task)
+        })
+        .unzip();
+    let results = join_all(tasks).await;
+
+    // A task that panicked still counts as a failed download of its episode
+    episodes
+        .into_iter()
+        .zip(results)
+        .map(|(episode, joined)| {
+            joined.unwrap_or_else(|e| {
+                let error = Error::Download { url: episode.media_url.clone(), reason: e.to_string() };
+                (episode, Err(error))
+            })
+        })
+        .collect()
+}
+
+// Partial downloads live next to the destination as `<name>.part`
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+async fn download_episode(client: &Client, episode: &Episode, dest: &Path, write_tags: bool) -> Result<Outcome> {
+    if fs::metadata(dest).await.is_ok() {
+        return Ok(Outcome::AlreadyPresent(dest.to_path_buf()));
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await.map_err(Error::io(parent))?;
+    }
+
+    let part = part_path(dest);
+    let download_error = |reason: String| Error::Download { url: episode.media_url.clone(), reason };
+
+    // Resume a previous partial download with an HTTP Range request
+    let offset = fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(&episode.media_url);
+    if offset > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", offset));
+    }
+    let stalled = || download_error(format!("no data for {} seconds", IDLE_TIMEOUT.as_secs()));
+    let mut resp = timeout(IDLE_TIMEOUT, request.send())
+        .await
+        .map_err(|_| stalled())?
+        .map_err(|e| download_error(e.to_string()))?;
+
+    let (mut file, mut written, expected) = match resp.status() {
+        StatusCode::PARTIAL_CONTENT if offset > 0 => {
+            let file = OpenOptions::new().append(true).open(&part).await.map_err(Error::io(&part))?;
+            (file, offset, resp.content_length().map(|len| offset + len))
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+            // The partial file may already be complete: the server reports the full size as `bytes */<size>`
+            if total_from_content_range(&resp) == Some(offset) {
+                return finish(client, episode, &part, dest, write_tags).await;
+            }
+            fs::remove_file(&part).await.map_err(Error::io(&part))?;
+            return Err(download_error("partial download no longer matches the server; discarded it".to_string()));
+        }
+        status if status.is_success() => {
+            // No range support (or a fresh start): begin from scratch
+            let file = File::create(&part).await.map_err(Error::io(&part))?;
+            (file, 0, resp.content_length())
+        }
+        status => return Err(download_error(format!("HTTP {}", status))),
+    };
+
+    if let (Some(expected), Some(advertised)) = (expected, episode.enclosure_length) {
+        if expected != advertised {
+            eprintln!(
+                "Warning: {} is {} bytes, but the feed advertises {} bytes",
+                episode.media_url, expected, advertised
+            );
+        }
+    }
+
+    while let Some(chunk) = timeout(IDLE_TIMEOUT, resp.chunk())
+        .await
+        .map_err(|_| stalled())?
+        .map_err(|e| download_error(e.to_string()))?
+    {
+        file.write_all(&chunk).await.map_err(Error::io(&part))?;
+        written += chunk.len() as u64;
+    }
+    file.flush().await.map_err(Error::io(&part))?;
+    file.sync_all().await.map_err(Error::io(&part))?;
+    drop(file);
+
+    // Keep the .part file around so the next run can resume it
+    if let Some(expected) = expected {
+        if written != expected {
+            return Err(download_error(format!("incomplete download: {} of {} bytes", written, expected)));
+        }
+    }
+
+    finish(client, episode, &part, dest, write_tags).await
+}
+
+// Tag the complete .part file, then move it into place
+async fn finish(client: &Client, episode: &Episode, part: &Path, dest: &Path, tag: bool) -> Result<Outcome> {
+    if tag {
+        let cover = match &episode.image_url {
+            Some(url) => timeout(IDLE_TIMEOUT, fetch_cover(client, url)).await.ok().flatten(),
+            None => None,
+        };
+        let extension = dest.extension().and_then(|e| e.to_str()).unwrap_or_default().to_string();
+        let (part_path, episode) = (part.to_path_buf(), episode.clone());
+        let tagged = tokio::task::spawn_blocking(move || write_tags(&part_path, &extension, &episode, cover.as_ref()))
+            .await
+            .unwrap_or_else(|e| Err(Error::Tag { path: part.display().to_string(), reason: e.to_string() }));
+        // A file with stale tags is still a usable download
+        if let Err(e) = tagged {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    fs::rename(part, dest).await.map_err(Error::io(dest))?;
+    Ok(Outcome::Downloaded(dest.to_path_buf()))
+}
+
+async fn fetch_cover(client: &Client, url: &str) -> Option<Cover> {
+    let resp = client.get(url).send().await.ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let mime_type = resp.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or_default().trim().to_ascii_lowercase())
+        .filter(|v| v.starts_with("image/"))
+        .or_else(|| {
+            let lower = url.to_ascii_lowercase();
+            if lower.contains(".png") { Some("image/png".to_string()) } else { Some("image/jpeg".to_string()) }
+        })?;
+    let data = resp.bytes().await.ok()?.to_vec();
+    Some(Cover { mime_type, data })
+}
+
+fn total_from_content_range(resp: &reqwest::Response) -> Option<u64> {
+    resp.headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
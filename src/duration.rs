@@ -0,0 +1,69 @@
+use crate::error::{Error, Result};
+
+/// Parse an episode length as feeds write it: `H:MM:SS`, `MM:SS` or plain seconds,
+/// with or without fractions. Returns whole seconds; zero lengths count as unknown.
+pub fn parse_feed_duration(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.trim().parse().ok()?;
+        if !(0.0..1e7).contains(&part) {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Some(seconds.round() as u32).filter(|&s| s > 0)
+}
+
+/// Parse a duration given on the command line: `90m`, `1h30m`, `3h`, `45s`, `1:30:00`,
+/// or a bare number of minutes. Zero is refused, as it would select nothing.
+pub fn parse_arg(flag: &str, value: &str) -> Result<u32> {
+    let invalid = || Error::InvalidArgument(format!("{} expects a duration like 45m, 1h30m or 1:30:00, got '{}'", flag, value));
+    let value = value.trim();
+    if value.contains(':') {
+        return parse_feed_duration(value).ok_or_else(invalid);
+    }
+    if let Ok(minutes) = value.parse::<u32>() {
+        return minutes.checked_mul(60).filter(|&seconds| seconds > 0).ok_or_else(invalid);
+    }
+
+    let mut total = 0u32;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let amount: u32 = number.parse().map_err(|_| invalid())?;
+        total = amount.checked_mul(unit).and_then(|seconds| total.checked_add(seconds)).ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// Format seconds as `1h 05m`, `42m` or `50s`.
+pub fn format(seconds: u32) -> String {
+    let (hours, minutes) = (seconds / 3600, seconds % 3600 / 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", seconds),
+        (0, _) => format!("{}m", minutes),
+        _ => format!("{}h {:02}m", hours, minutes),
+    }
+}
+
+/// Format seconds as `H:MM:SS`, the way feeds write lengths.
+pub fn format_clock(seconds: u32) -> String {
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
@@ -0,0 +1,103 @@
+use std::{fmt, io, path::Path};
+
+/// Everything that can make a powercrust run fail.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file (or stdout) failed
+    Io { target: String, source: io::Error },
+    /// The episode database could not be opened, read or updated
+    Store { path: String, source: rusqlite::Error },
+    /// The HTTP client could not be set up
+    Client(reqwest::Error),
+    /// A single feed could not be fetched; reported as a warning, not fatal on its own
+    Fetch { feed: String, reason: String },
+    /// A single episode could not be downloaded; reported as a warning, not fatal on its own
+    Download { url: String, reason: String },
+    /// Tags could not be written into a downloaded file
+    Tag { path: String, reason: String },
+    /// The playback endpoint could not listen or stopped serving
+    Serve { addr: String, source: hyper::Error },
+    /// The OPML file did not list any feeds
+    NoFeeds(String),
+    /// Every feed failed to fetch, so there is nothing to write
+    AllFeedsFailed(usize),
+    /// Some feeds failed to fetch; outputs were still written from the rest
+    PartialFeedFailures { failed: usize, total: usize },
+    /// Some episodes could not be downloaded
+    DownloadFailures { failed: usize, total: usize },
+    /// Some files selected by the retention rules could not be deleted
+    PruneFailures { failed: usize, total: usize },
+    /// Some media URLs failed the link check
+    BrokenLinks { broken: usize, total: usize },
+    /// A command-line value could not be understood
+    InvalidArgument(String),
+    /// A line of an episode listing read back in could not be understood
+    Listing { source: String, line: usize, reason: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Build a mapper for `map_err` that attaches the path being read or written.
+    pub fn io(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+        move |source| Error::Io { target: path.display().to_string(), source }
+    }
+
+    pub fn store(path: impl fmt::Display, source: rusqlite::Error) -> Error {
+        Error::Store { path: path.to_string(), source }
+    }
+
+    /// Process exit code, so cron jobs can tell failure kinds apart.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Client(_) | Error::Fetch { .. } | Error::Download { .. } | Error::Tag { .. } | Error::Serve { .. } => 1,
+            Error::InvalidArgument(_) | Error::Listing { .. } => 2,
+            Error::NoFeeds(_) | Error::AllFeedsFailed(_) => 3,
+            Error::PartialFeedFailures { .. } | Error::DownloadFailures { .. } | Error::PruneFailures { .. } => 4,
+            Error::Io { .. } | Error::Store { .. } => 5,
+            Error::BrokenLinks { .. } => 6,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { target, source } => write!(f, "I/O error on {}: {}", target, source),
+            Error::Store { path, source } => write!(f, "episode database {}: {}", path, source),
+            Error::Client(e) => write!(f, "cannot set up HTTP client: {}", e),
+            Error::Fetch { feed, reason } => write!(f, "cannot fetch feed '{}': {}", feed, reason),
+            Error::Download { url, reason } => write!(f, "cannot download {}: {}", url, reason),
+            Error::Tag { path, reason } => write!(f, "cannot tag {}: {}", path, reason),
+            Error::Serve { addr, source } => write!(f, "cannot serve on {}: {}", addr, source),
+            Error::NoFeeds(path) => write!(f, "no feeds found in {}", path),
+            Error::AllFeedsFailed(total) => write!(f, "all {} feeds failed to fetch", total),
+            Error::PartialFeedFailures { failed, total } => {
+                write!(f, "{} of {} feeds failed to fetch", failed, total)
+            }
+            Error::DownloadFailures { failed, total } => {
+                write!(f, "{} of {} downloads failed", failed, total)
+            }
+            Error::PruneFailures { failed, total } => {
+                write!(f, "{} of {} files could not be pruned", failed, total)
+            }
+            Error::BrokenLinks { broken, total } => {
+                write!(f, "{} of {} media URLs are broken", broken, total)
+            }
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            Error::Listing { source, line, reason } => write!(f, "{} line {}: {}", source, line, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Store { source, .. } => Some(source),
+            Error::Client(e) => Some(e),
+            Error::Serve { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use chrono::{DateTime, Utc};
+
+use crate::dates::parse_date;
+use crate::duration::parse_feed_duration;
+use regex::Regex;
+use rss::{Channel, Item};
+use std::{collections::HashMap, sync::OnceLock};
+
+#[derive(Clone)]
+pub struct Episode {
+    pub feed_name: String,
+    pub feed_url: String,
+    /// OPML folder the feed is filed under, if any
+    pub group: Option<String>,
+    /// Item guid, or the media URL for items without one
+    pub guid: String,
+    pub title: String,
+    pub pub_date: Option<DateTime<Utc>>,
+    pub media_url: String,
+    /// Enclosure length in bytes as advertised by the feed, if any
+    pub enclosure_length: Option<u64>,
+    /// Enclosure MIME type as advertised by the feed, if any
+    pub enclosure_type: Option<String>,
+    /// Show notes as plain text
+    pub description: Option<String>,
+    /// `itunes:episode`, if the feed numbers its episodes
+    pub episode_number: Option<u32>,
+    /// Episode artwork, falling back to the channel artwork
+    pub image_url: Option<String>,
+    /// Length in seconds, from `itunes:duration`, `media:content` or estimated from the enclosure
+    pub duration: Option<u32>,
+    /// Why the last `check` found the media URL broken, if it did
+    pub broken: Option<String>,
+    /// Artwork to show: its path in the `--covers` cache, or `image_url` if it could not be cached
+    pub cover: Option<String>,
+    /// The OPML lists the feed came from, when several were given
+    pub sources: Vec<String>,
+}
+
+/// A feed listed in the OPML file.
+pub struct Subscription {
+    pub name: String,
+    pub url: String,
+    /// Text of the enclosing `<outline>` folder, if the feed is nested in one
+    pub group: Option<String>,
+    /// The outline's `imageUrl`, used when the feed itself has no artwork
+    pub image_url: Option<String>,
+    /// Names of the OPML lists that include the feed, when several were given
+    pub sources: Vec<String>,
+}
+
+/// Merge the subscriptions of several OPML lists, given with their names. A feed listed
+/// more than once (by normalized URL) keeps its first name, URL and folder, and gathers
+/// the names of every list that has it. With a single list, `sources` stay empty.
+pub fn merge_subscriptions(lists: Vec<(String, Vec<Subscription>)>) -> Vec<Subscription> {
+    let several = lists.len() > 1;
+    let mut merged: Vec<Subscription> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (list, subscriptions) in lists {
+        for mut subscription in subscriptions {
+            let key = normalize_feed_url(&subscription.url);
+            match index.get(&key) {
+                Some(&i) => {
+                    let kept = &mut merged[i];
+                    if several && !kept.sources.contains(&list) {
+                        kept.sources.push(list.clone());
+                    }
+                    if kept.image_url.is_none() {
+                        kept.image_url = subscription.image_url;
+                    }
+                }
+                None => {
+                    if several {
+                        subscription.sources = vec![list.clone()];
+                    }
+                    index.insert(key, merged.len());
+                    merged.push(subscription);
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// A feed URL reduced to what identifies the feed: no scheme (`http`, `https`, `feed`,
+/// `itpc` and `pcast` all name the same feed), no `www.`, no fragment or trailing slash,
+/// and a lowercase host.
+pub fn normalize_feed_url(url: &str) -> String {
+    let url = url.trim().replace("&amp;", "&");
+    let rest = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest);
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (host, path) = rest.split_once('/').map_or((rest, ""), |(host, path)| (host, path));
+    let host = host.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let path = path.trim_end_matches('/');
+    if path.is_empty() { host.to_string() } else { format!("{}/{}", host, path) }
+}
+
+/// Raw XML of a feed fetched from a subscription.
+pub struct FetchedFeed {
+    pub subscription: Subscription,
+    pub content: String,
+    /// The response's Last-Modified header, if the server sent one
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Episodes of one feed, plus the publication dates that could not be understood.
+#[derive(Default)]
+pub struct ParsedFeed {
+    pub episodes: Vec<Episode>,
+    pub unparsed_dates: Vec<String>,
+}
+
+// Parse every item of a feed that carries a media URL into an Episode
+pub fn parse_episodes(feed: &FetchedFeed, media_regex: &Regex) -> ParsedFeed {
+    let channel = match Channel::read_from(feed.content.as_bytes()) {
+        Ok(channel) => channel,
+        Err(_) => return ParsedFeed::default(),
+    };
+
+    let channel_image = channel.itunes_ext()
+        .and_then(|itunes| itunes.image())
+        .or_else(|| channel.image().map(|image| image.url()))
+        .map(|url| url.to_string())
+        .or_else(|| feed.subscription.image_url.clone());
+
+    let mut parsed = ParsedFeed::default();
+    for item in channel.items() {
+        if let Some(episode) = parse_item(feed, item, channel_image.as_deref(), media_regex, &mut parsed.unparsed_dates) {
+            parsed.episodes.push(episode);
+        }
+    }
+    parsed
+}
+
+fn parse_item(
+    feed: &FetchedFeed,
+    item: &Item,
+    channel_image: Option<&str>,
+    media_regex: &Regex,
+    unparsed_dates: &mut Vec<String>,
+) -> Option<Episode> {
+    let enclosure_length = item.enclosure()
+        .and_then(|e| e.length.trim().parse::<u64>().ok())
+        .filter(|&len| len > 0);
+    let enclosure_type = item.enclosure()
+        .map(|e| e.mime_type.trim().to_string())
+        .filter(|t| !t.is_empty());
+
+    // === MEDIA URL extraction ===
+    let media_url = if let Some(enclosure) = item.enclosure() {
+        // Prefer <enclosure url="...">
+        enclosure.url.clone()
+    } else {
+        // Fallback: scrape from description + content:encoded
+        let description = item.description().unwrap_or_default();
+        let content_encoded = item.extensions().get("content")
+            .and_then(|c| c.get("encoded"))
+            .and_then(|e| e.first())
+            .and_then(|v| v.value.as_deref())
+            .unwrap_or("");
+
+        let combined = format!("{} {}", description, content_encoded);
+
+        media_regex.captures(&combined)?.get(1)?.as_str().to_string()
+    };
+
+    // === PUB DATE fallback ===
+    let date_str = item.pub_date()
+        .or_else(|| {
+            item.extensions().get("dc")
+                .and_then(|m| m.get("date"))
+                .and_then(|e| e.first())
+                .and_then(|e| e.value.as_deref())
+        });
+
+    let pub_date = date_str.and_then(|s| {
+        let date = parse_date(s);
+        if date.is_none() && !s.trim().is_empty() {
+            unparsed_dates.push(s.trim().to_string());
+        }
+        date
+    });
+
+    // === TITLE fallback ===
+    let title = item.title()
+        .map(|s| s.to_string())
+        .or_else(|| {
+            item.extensions().get("itunes")
+                .and_then(|m| m.get("title"))
+                .and_then(|e| e.first())
+                .and_then(|e| e.value.clone())
+        })
+        .or_else(|| {
+            item.extensions().get("media")
+                .and_then(|m| m.get("title"))
+                .and_then(|e| e.first())
+                .and_then(|e| e.value.clone())
+        })
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    // === DESCRIPTION, EPISODE NUMBER, ARTWORK ===
+    let description = item.description()
+        .or_else(|| item.itunes_ext().and_then(|itunes| itunes.summary()))
+        .map(plain_text)
+        .filter(|d| !d.is_empty());
+    let episode_number = item.itunes_ext()
+        .and_then(|itunes| itunes.episode())
+        .and_then(|n| n.trim().parse::<u32>().ok());
+    let image_url = item.itunes_ext()
+        .and_then(|itunes| itunes.image())
+        .or(channel_image)
+        .map(|url| url.to_string());
+
+    let duration = item.itunes_ext()
+        .and_then(|itunes| itunes.duration())
+        .and_then(parse_feed_duration)
+        .or_else(|| {
+            item.extensions().get("media")
+                .and_then(|m| m.get("content"))
+                .and_then(|e| e.first())
+                .and_then(|e| e.attrs.get("duration"))
+                .and_then(|d| parse_feed_duration(d))
+        })
+        .or_else(|| estimate_duration(enclosure_length, enclosure_type.as_deref()));
+
+    let guid = item.guid()
+        .map(|g| g.value().to_string())
+        .filter(|g| !g.is_empty())
+        .unwrap_or_else(|| media_url.clone());
+
+    Some(Episode {
+        feed_name: feed.subscription.name.clone(),
+        feed_url: feed.subscription.url.clone(),
+        group: feed.subscription.group.clone(),
+        guid,
+        title,
+        pub_date,
+        media_url,
+        enclosure_length,
+        enclosure_type,
+        description,
+        episode_number,
+        image_url,
+        duration,
+        broken: None,
+        cover: None,
+        sources: feed.subscription.sources.clone(),
+    })
+}
+
+// MP3 enclosures without a stated length are assumed to be 128 kbps, the most common
+// podcast bitrate. Other formats vary too much to guess, as do implausibly small files.
+fn estimate_duration(length: Option<u64>, mime_type: Option<&str>) -> Option<u32> {
+    const BYTES_PER_SECOND: u64 = 128_000 / 8;
+    match (length, mime_type) {
+        (Some(length), Some("audio/mpeg" | "audio/mp3")) if length >= 100_000 => {
+            Some((length / BYTES_PER_SECOND) as u32)
+        }
+        _ => None,
+    }
+}
+
+// Show notes are usually HTML; keep the text, one paragraph per line
+fn plain_text(html: &str) -> String {
+    static BREAKS: OnceLock<Regex> = OnceLock::new();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    let breaks = BREAKS.get_or_init(|| Regex::new(r"(?i)<br\s*/?>|</p>|</li>").unwrap());
+    let tags = TAGS.get_or_init(|| Regex::new(r"<[^>]*>").unwrap());
+    let text = breaks.replace_all(html, "\n");
+    let text = tags.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,190 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+
+/// What a single filter option looks at and whether it keeps or drops matches.
+#[derive(Clone, Copy)]
+pub enum FilterKind {
+    IncludeTitle,
+    ExcludeTitle,
+    IncludeFeed,
+    ExcludeFeed,
+    IncludeDescription,
+    ExcludeDescription,
+}
+
+/// Include/exclude rules for one scope. An episode passes when it matches at least
+/// one include rule of each kind that has any, and no exclude rule.
+#[derive(Default)]
+pub struct FilterSet {
+    include_title: Vec<Regex>,
+    exclude_title: Vec<Regex>,
+    include_feed: Vec<Regex>,
+    exclude_feed: Vec<Regex>,
+    // Lowercased keywords, matched as substrings of the description
+    include_description: Vec<String>,
+    exclude_description: Vec<String>,
+}
+
+impl FilterSet {
+    /// Add a rule. Title and feed patterns are case-insensitive regexes,
+    /// description keywords are case-insensitive substrings.
+    pub fn add(&mut self, kind: FilterKind, pattern: &str) -> Result<()> {
+        let regex = || {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| Error::InvalidArgument(format!("bad filter pattern '{}': {}", pattern, e)))
+        };
+        match kind {
+            FilterKind::IncludeTitle => self.include_title.push(regex()?),
+            FilterKind::ExcludeTitle => self.exclude_title.push(regex()?),
+            FilterKind::IncludeFeed => self.include_feed.push(regex()?),
+            FilterKind::ExcludeFeed => self.exclude_feed.push(regex()?),
+            FilterKind::IncludeDescription => self.include_description.push(pattern.to_lowercase()),
+            FilterKind::ExcludeDescription => self.exclude_description.push(pattern.to_lowercase()),
+        }
+        Ok(())
+    }
+
+    fn matches_feed(&self, episode: &Episode) -> bool {
+        (self.include_feed.is_empty() || self.include_feed.iter().any(|r| r.is_match(&episode.feed_name)))
+            && !self.exclude_feed.iter().any(|r| r.is_match(&episode.feed_name))
+    }
+
+    fn matches(&self, episode: &Episode) -> bool {
+        let description = episode.description.as_deref().unwrap_or_default().to_lowercase();
+        let any_regex = |rules: &[Regex], text: &str| rules.iter().any(|r| r.is_match(text));
+        let any_keyword = |rules: &[String]| rules.iter().any(|k| description.contains(k.as_str()));
+
+        (self.include_title.is_empty() || any_regex(&self.include_title, &episode.title))
+            && !any_regex(&self.exclude_title, &episode.title)
+            && self.matches_feed(episode)
+            && (self.include_description.is_empty() || any_keyword(&self.include_description))
+            && !any_keyword(&self.exclude_description)
+    }
+}
+
+/// Filters for every feed, plus extra filters for the feeds in particular OPML folders.
+#[derive(Default)]
+pub struct Filters {
+    pub global: FilterSet,
+    pub groups: Vec<(String, FilterSet)>,
+    /// Length bounds in seconds; episodes of unknown length always pass
+    pub min_duration: Option<u32>,
+    pub max_duration: Option<u32>,
+}
+
+impl Filters {
+    /// Whether an episode passes the length bounds, the global filters and those of its feed group.
+    pub fn matches(&self, episode: &Episode) -> bool {
+        let duration_ok = episode.duration.is_none_or(|duration| {
+            self.min_duration.is_none_or(|min| duration >= min) && self.max_duration.is_none_or(|max| duration <= max)
+        });
+        duration_ok
+            && self.global.matches(episode)
+            && self.groups
+                .iter()
+                .filter(|(group, _)| episode.group.as_deref() == Some(group.as_str()))
+                .all(|(_, filters)| filters.matches(episode))
+    }
+
+    /// Whether an episode passes the rules on feed names alone, for episodes known only by
+    /// their media URL and feed.
+    pub fn matches_feed(&self, episode: &Episode) -> bool {
+        self.global.matches_feed(episode)
+            && self.groups
+                .iter()
+                .filter(|(group, _)| episode.group.as_deref() == Some(group.as_str()))
+                .all(|(_, filters)| filters.matches_feed(episode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn episode(feed: &str, group: Option<&str>, title: &str, description: Option<&str>, minutes: Option<u32>) -> Episode {
+        Episode {
+            feed_name: feed.to_string(),
+            feed_url: format!("https://example.com/{}.xml", feed),
+            group: group.map(str::to_string),
+            guid: title.to_string(),
+            title: title.to_string(),
+            pub_date: None,
+            media_url: format!("https://example.com/{}.mp3", title),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: description.map(str::to_string),
+            episode_number: None,
+            image_url: None,
+            duration: minutes.map(|m| m * 60),
+            broken: None,
+            cover: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn set(rules: &[(FilterKind, &str)]) -> FilterSet {
+        let mut set = FilterSet::default();
+        for &(kind, pattern) in rules {
+            set.add(kind, pattern).unwrap();
+        }
+        set
+    }
+
+    #[test]
+    fn includes_any_and_excludes_every_match() {
+        let filters = Filters {
+            global: set(&[
+                (FilterKind::IncludeTitle, "interview"),
+                (FilterKind::IncludeTitle, "^Q&A"),
+                (FilterKind::ExcludeTitle, r"\brerun\b"),
+                (FilterKind::ExcludeDescription, "SPONSORED"),
+            ]),
+            ..Filters::default()
+        };
+        assert!(filters.matches(&episode("News", None, "An INTERVIEW", None, None)));
+        assert!(filters.matches(&episode("News", None, "Q&A #3", Some("Listener questions"), None)));
+        assert!(!filters.matches(&episode("News", None, "Weekly roundup", None, None)));
+        assert!(!filters.matches(&episode("News", None, "Interview (rerun)", None, None)));
+        assert!(!filters.matches(&episode("News", None, "Interview", Some("This episode is sponsored by"), None)));
+
+        let keywords = Filters { global: set(&[(FilterKind::IncludeDescription, "Climate")]), ..Filters::default() };
+        assert!(keywords.matches(&episode("News", None, "Any", Some("On climate policy"), None)));
+        assert!(!keywords.matches(&episode("News", None, "Any", None, None)));
+    }
+
+    #[test]
+    fn group_rules_apply_to_their_folder_only() {
+        let filters = Filters {
+            global: set(&[(FilterKind::ExcludeFeed, "^Old ")]),
+            groups: vec![("News".to_string(), set(&[(FilterKind::IncludeTitle, "interview")]))],
+            ..Filters::default()
+        };
+        assert!(filters.matches(&episode("Daily", Some("Comedy"), "Sketches", None, None)));
+        assert!(!filters.matches(&episode("Daily", Some("News"), "Sketches", None, None)));
+        assert!(filters.matches(&episode("Daily", Some("News"), "The interview", None, None)));
+        assert!(!filters.matches(&episode("Old Daily", None, "The interview", None, None)));
+
+        // Known only by feed: the title rules of the folder cannot be judged, the feed rules can
+        assert!(filters.matches_feed(&episode("Daily", Some("News"), "Unknown", None, None)));
+        assert!(!filters.matches_feed(&episode("Old Daily", None, "Unknown", None, None)));
+    }
+
+    #[test]
+    fn unknown_lengths_pass_length_bounds() {
+        let filters = Filters { min_duration: Some(10 * 60), max_duration: Some(60 * 60), ..Filters::default() };
+        assert!(filters.matches(&episode("News", None, "Any", None, Some(30))));
+        assert!(filters.matches(&episode("News", None, "Any", None, Some(60))));
+        assert!(!filters.matches(&episode("News", None, "Any", None, Some(5))));
+        assert!(!filters.matches(&episode("News", None, "Any", None, Some(61))));
+        assert!(filters.matches(&episode("News", None, "Any", None, None)));
+    }
+
+    #[test]
+    fn rejects_bad_patterns() {
+        assert!(matches!(FilterSet::default().add(FilterKind::IncludeTitle, "(unclosed"), Err(Error::InvalidArgument(_))));
+    }
+}
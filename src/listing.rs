@@ -0,0 +1,382 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::duration;
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+
+/// Version of the text listing written by this build.
+pub const VERSION: u32 = 2;
+
+// First line of a listing; lines starting with '#' are otherwise comments
+const HEADER_PREFIX: &str = "# powercrust episodes v";
+
+/// Write the header line that marks a text listing and its version.
+pub fn write_header<W: Write + ?Sized>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}{}", HEADER_PREFIX, VERSION)
+}
+
+/// Write one episode as a listing line:
+///
+/// `FEED: TITLE [DATE] [cover: PATH] [length: H:MM:SS] [from: LIST, LIST] - URL (broken: REASON)`
+///
+/// DATE is `YYYY-MM-DD` or `Unknown date`; the bracketed fields and the broken note are
+/// only there when known. A backslash escapes `:` in the feed name, `[` and `]` in the
+/// title and field values, `,` in list names, a `#` that starts the line, and itself; line breaks
+/// become spaces and spaces in the URL `%20`, so the URL is always the last word before any broken note.
+pub fn write_episode<W: Write + ?Sized>(out: &mut W, episode: &Episode) -> io::Result<()> {
+    let mut feed = escape(&episode.feed_name, &[':']);
+    // A line starting with `#` is a comment
+    if feed.starts_with('#') {
+        feed.insert(0, '\\');
+    }
+    write!(
+        out,
+        "{}: {} [{}]",
+        feed,
+        escape(&episode.title, &['[', ']']),
+        episode.pub_date.map_or_else(|| "Unknown date".to_string(), |d| d.format("%Y-%m-%d").to_string())
+    )?;
+    if let Some(cover) = &episode.cover {
+        write!(out, " [cover: {}]", escape(cover, &['[', ']']))?;
+    }
+    if let Some(seconds) = episode.duration {
+        write!(out, " [length: {}]", duration::format_clock(seconds))?;
+    }
+    if !episode.sources.is_empty() {
+        let sources: Vec<String> = episode.sources.iter().map(|s| escape(s, &['[', ']', ','])).collect();
+        write!(out, " [from: {}]", sources.join(", "))?;
+    }
+    write!(out, " - {}", episode.media_url.trim().replace(' ', "%20"))?;
+    match &episode.broken {
+        Some(problem) => writeln!(out, " (broken: {})", one_line(problem)),
+        None => writeln!(out),
+    }
+}
+
+fn one_line(s: &str) -> String {
+    s.split(['\r', '\n', '\t']).filter(|part| !part.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in one_line(s).chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Read a listing written by powercrust back into episodes. Files without the header
+/// line are read as the unescaped format of earlier versions, as well as it allows.
+pub fn read(path: &Path) -> Result<Vec<Episode>> {
+    let text = std::fs::read_to_string(path).map_err(Error::io(path))?;
+    parse(&text, &path.display().to_string())
+}
+
+/// Parse listing text; `source` names it in errors. Read episodes have no feed URL,
+/// description or group, and their guid is the media URL.
+pub fn parse(text: &str, source: &str) -> Result<Vec<Episode>> {
+    let invalid = |line: usize, reason: String| Error::Listing { source: source.to_string(), line, reason };
+
+    let mut lines = text.lines().enumerate().peekable();
+    let version = match lines.peek().and_then(|(_, line)| line.strip_prefix(HEADER_PREFIX)) {
+        Some(version) => {
+            let version = version
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| invalid(1, format!("unreadable version '{}'", version.trim())))?;
+            if version > VERSION {
+                return Err(invalid(1, format!("written in version {}, this build reads up to {}", version, VERSION)));
+            }
+            lines.next();
+            version
+        }
+        None => 1,
+    };
+
+    let mut episodes = Vec::new();
+    for (index, line) in lines {
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let episode = if version == 1 { parse_v1(line) } else { parse_v2(line) };
+        episodes.push(episode.map_err(|reason| invalid(index + 1, reason))?);
+    }
+    Ok(episodes)
+}
+
+// Fields of a line, before they become an episode
+struct Line {
+    feed: String,
+    title: String,
+    date: String,
+    cover: Option<String>,
+    length: Option<String>,
+    sources: Vec<String>,
+    url: String,
+    broken: Option<String>,
+}
+
+fn parse_v2(line: &str) -> std::result::Result<Episode, String> {
+    let mut rest = line;
+    let feed = take_escaped(&mut rest, ':').ok_or("no ': ' after the feed name")?;
+    rest = rest.strip_prefix(' ').ok_or("no ': ' after the feed name")?;
+    let title = take_escaped(&mut rest, '[').ok_or("no [date] after the title")?;
+    let (date, after) = rest.split_once(']').ok_or("unclosed [date]")?;
+    rest = after;
+
+    let (mut cover, mut length, mut sources) = (None, None, Vec::new());
+    while let Some(field) = rest.strip_prefix(" [") {
+        rest = field;
+        // Kept escaped until the key is known, since list names escape their separator
+        let field = take_raw(&mut rest, ']').ok_or("unclosed [field]")?;
+        let (key, value) = field.split_once(": ").ok_or_else(|| format!("field '[{}]' has no 'key: '", unescape(field)))?;
+        match key {
+            "cover" => cover = Some(unescape(value)),
+            "length" => length = Some(unescape(value)),
+            "from" => {
+                let mut value = value;
+                while let Some(source) = take_raw(&mut value, ',') {
+                    sources.push(unescape(source));
+                    value = value.strip_prefix(' ').unwrap_or(value);
+                }
+                sources.push(unescape(value));
+            }
+            // Fields added later within this version are skipped
+            _ => {}
+        }
+    }
+
+    rest = rest.strip_prefix(" - ").ok_or("no ' - ' before the URL")?;
+    let (url, after) = rest.split_once(' ').unwrap_or((rest, ""));
+    let broken = match after {
+        "" => None,
+        note => Some(
+            note.strip_prefix("(broken: ")
+                .and_then(|reason| reason.strip_suffix(')'))
+                .ok_or_else(|| format!("unexpected '{}' after the URL", note))?
+                .to_string(),
+        ),
+    };
+    Line {
+        feed,
+        title: title.strip_suffix(' ').unwrap_or(&title).to_string(),
+        date: date.to_string(),
+        cover,
+        length,
+        sources,
+        url: url.to_string(),
+        broken,
+    }
+    .into_episode()
+}
+
+// Version 1 lines had no escaping: the feed name ends at the first ': ', the URL follows
+// the last ' - ' (perhaps with a broken note), and the date is the last bracket before it
+// that is not a cover
+fn parse_v1(line: &str) -> std::result::Result<Episode, String> {
+    let (feed, rest) = line.split_once(": ").ok_or("no ': ' after the feed name")?;
+    let (mut rest, url) = rest.rsplit_once(" - ").ok_or("no ' - ' before the URL")?;
+    let (url, note) = url.trim().split_once(' ').unwrap_or((url.trim(), ""));
+    let broken = note.strip_prefix("(broken: ").and_then(|reason| reason.strip_suffix(')')).map(str::to_string);
+
+    let mut cover = None;
+    if let Some(start) = rest.rfind(" [cover: ").filter(|_| rest.ends_with(']')) {
+        cover = Some(rest[start + 9..rest.len() - 1].to_string());
+        rest = &rest[..start];
+    }
+    let mut date = "Unknown date";
+    if let Some(start) = rest.rfind(" [").filter(|_| rest.ends_with(']')) {
+        date = &rest[start + 2..rest.len() - 1];
+        rest = &rest[..start];
+    }
+    Line {
+        feed: feed.to_string(),
+        title: rest.to_string(),
+        date: date.to_string(),
+        cover,
+        length: None,
+        sources: Vec::new(),
+        url: url.to_string(),
+        broken,
+    }
+    .into_episode()
+}
+
+// Everything up to the first unescaped `end`, unescaped; `rest` moves past the `end`
+fn take_escaped(rest: &mut &str, end: char) -> Option<String> {
+    take_raw(rest, end).map(unescape)
+}
+
+// Everything up to the first unescaped `end`, still escaped; `rest` moves past the `end`
+fn take_raw<'a>(rest: &mut &'a str, end: char) -> Option<&'a str> {
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == end {
+            let value = &rest[..i];
+            *rest = &rest[i + c.len_utf8()..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn unescape(s: &str) -> String {
+    let mut value = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        value.push(if c == '\\' { chars.next().unwrap_or('\\') } else { c });
+    }
+    value
+}
+
+impl Line {
+    fn into_episode(self) -> std::result::Result<Episode, String> {
+        let pub_date = match self.date.as_str() {
+            "Unknown date" => None,
+            date => {
+                let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("unreadable date '{}'", date))?;
+                Some(Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).unwrap()))
+            }
+        };
+        if !self.url.contains("://") {
+            return Err(format!("'{}' is not a URL", self.url));
+        }
+        let duration = match &self.length {
+            Some(length) => Some(duration::parse_feed_duration(length).ok_or_else(|| format!("unreadable length '{}'", length))?),
+            None => None,
+        };
+        Ok(Episode {
+            feed_name: self.feed,
+            feed_url: String::new(),
+            group: None,
+            guid: self.url.clone(),
+            title: self.title,
+            pub_date,
+            media_url: self.url,
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: self.cover.clone(),
+            duration,
+            broken: self.broken,
+            cover: self.cover,
+            sources: self.sources,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, write_episode, write_header};
+    use crate::feed::Episode;
+    use chrono::{TimeZone, Utc};
+
+    // Feed names, titles and list names that need escaping, or look like the syntax around them
+    const FEEDS: &[&str] = &["Plain Feed", "Feed: The Sequel", "C:\\Podcasts\\", "Ends with colon:", "[Bracketed]", "#1 Podcast"];
+    const TITLES: &[&str] = &[
+        "Plain title",
+        "",
+        "Ep 2: New - stuff",
+        "[Rerun] The [best] of",
+        "Unbalanced ] and [",
+        "Back\\slash \\[not a field\\]",
+        "Trailing backslash\\",
+        "[2026-09-01]",
+        "Looks like [cover: x.jpg] a field",
+        "Ends with a space ",
+        "Ünïcödé – 日本語",
+    ];
+    const SOURCES: &[&[&str]] = &[&[], &["work"], &["work, team", "home [old]"], &["a\\b", ","]];
+
+    fn episode(feed: &str, title: &str, sources: &[&str], n: usize) -> Episode {
+        Episode {
+            feed_name: feed.to_string(),
+            feed_url: String::new(),
+            group: None,
+            guid: format!("https://example.com/{}.mp3", n),
+            title: title.to_string(),
+            pub_date: n.is_multiple_of(2).then(|| Utc.with_ymd_and_hms(2026, 9, 1, 0, 0, 0).unwrap()),
+            media_url: format!("https://example.com/{}.mp3", n),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: n.is_multiple_of(3).then(|| "covers/[a]b.jpg".to_string()),
+            duration: n.is_multiple_of(4).then_some(3723),
+            broken: n.is_multiple_of(5).then(|| "HTTP 404: gone (really)".to_string()),
+            cover: n.is_multiple_of(3).then(|| "covers/[a]b.jpg".to_string()),
+            sources: sources.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn fields(e: &Episode) -> impl PartialEq + std::fmt::Debug {
+        (
+            e.feed_name.clone(),
+            e.title.clone(),
+            e.pub_date,
+            e.media_url.clone(),
+            e.cover.clone(),
+            e.duration,
+            e.broken.clone(),
+            e.sources.clone(),
+        )
+    }
+
+    #[test]
+    fn round_trips_awkward_text() {
+        let mut episodes = Vec::new();
+        for feed in FEEDS {
+            for title in TITLES {
+                let n = episodes.len();
+                episodes.push(episode(feed, title, SOURCES[n % SOURCES.len()], n));
+            }
+        }
+        let mut text = Vec::new();
+        write_header(&mut text).unwrap();
+        for episode in &episodes {
+            write_episode(&mut text, episode).unwrap();
+        }
+        let text = String::from_utf8(text).unwrap();
+
+        let read = parse(&text, "test").unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(read.len(), episodes.len());
+        for (written, read) in episodes.iter().zip(&read) {
+            assert_eq!(fields(read), fields(written), "in listing:\n{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_unversioned_listings() {
+        let read = parse("Feed: Ep 2: New - stuff [2026-09-01] [cover: c.jpg] - https://example.com/a.mp3\n", "test").unwrap();
+        assert_eq!(read[0].feed_name, "Feed");
+        assert_eq!(read[0].title, "Ep 2: New - stuff");
+        assert_eq!(read[0].cover.as_deref(), Some("c.jpg"));
+        assert_eq!(read[0].media_url, "https://example.com/a.mp3");
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_broken_lines() {
+        assert!(parse("# powercrust episodes v99\n", "test").is_err());
+        let broken = [
+            "Feed: Title without date - https://x/a.mp3",
+            "Feed: Title [2026-09-01] - not a url",
+            "Feed: Title [2026-13-01] - https://x/a.mp3",
+            "Feed: Title [2026-09-01] [length: soon] - https://x/a.mp3",
+            "Feed: Title [2026-09-01] [cover: x.jpg - https://x/a.mp3",
+        ];
+        for line in broken {
+            assert!(parse(&format!("# powercrust episodes v2\n{}\n", line), "test").is_err(), "{:?} was accepted", line);
+        }
+    }
+}
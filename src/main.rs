@@ -1,619 +1,1597 @@
+mod atomic;
+mod check;
+mod covers;
+mod dates;
+mod dedup;
+mod diff;
+mod download;
+mod duration;
+mod error;
+mod feed;
+mod filter;
+mod listing;
+mod mp4;
+mod naming;
+mod output;
+mod retention;
+mod select;
+mod serve;
+mod stats;
+mod store;
+mod tags;
+mod trackers;
+mod undated;
+
 use regex::Regex;
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, ClientBuilder, StatusCode};
 use futures::future::join_all;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
-    io::{BufRead, BufReader, Write},
-    path::PathBuf,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::ExitCode,
     sync::Arc,
 };
 use tokio::sync::Semaphore as TokioSemaphore;
-use chrono::{DateTime, Utc, Duration};
-use rss::Channel;
-use clap::{App, Arg};
-
-#[derive(Clone)]
-struct Episode {
-    feed_name: String,
-    title: String,
-    pub_date: Option<DateTime<Utc>>,
-    media_url: String,
-}
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgMatches};
+
+use download::{download_episodes, DownloadOptions, Outcome};
+use error::{Error, Result};
+use feed::{parse_episodes, Episode, FetchedFeed, Subscription};
+use filter::{FilterKind, FilterSet, Filters};
+use output::{default_out_dir, OutputTarget, StagedOutputs};
+use retention::{Policy, Rules};
+use select::{Selection, Window};
+use store::{PlaybackChange, Store};
+use trackers::TrackerRules;
+use undated::{DateSource, Undated};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn run() -> Result<()> {
     // Use clap for command-line argument parsing
-    let matches = App::new("RSS Feed Scraper")
+    let matches = cli().get_matches();
+
+    match matches.subcommand() {
+        Some(("download", sub_matches)) => run_download(sub_matches).await,
+        Some(("prune", sub_matches)) => run_prune(sub_matches),
+        Some(("keep", sub_matches)) => run_keep(sub_matches),
+        Some(("mark-played", sub_matches)) => run_mark_played(sub_matches),
+        Some(("serve", sub_matches)) => run_serve(sub_matches).await,
+        Some(("check", sub_matches)) => run_check(sub_matches).await,
+        Some(("stats", sub_matches)) => run_stats(sub_matches).await,
+        Some(("merge", sub_matches)) => run_merge(sub_matches),
+        Some(("diff", sub_matches)) => run_diff(sub_matches),
+        _ => run_outputs(&matches).await,
+    }
+}
+
+fn cli() -> App<'static> {
+    App::new("RSS Feed Scraper")
         .version("0.3.0")
         .about("Scrapes RSS feeds from an OPML file and extracts media URLs")
         .before_help("                   ▗ 
 ▛▌▛▌▌▌▌█▌▛▘▛▘▛▘▌▌▛▘▜▘
 ▙▌▙▌▚▚▘▙▖▌ ▙▖▌ ▙▌▄▌▐▖
 ▌                    ")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .args(collection_args())
         .arg(
-            Arg::with_name("opml_file")
-                .help("Path to the OPML file containing RSS feeds")
-                .required(true)
-                .index(1),
+            Arg::with_name("formats")
+                .short('f')
+                .long("formats")
+                .help("Format(s) for output files (txt, md, html, player), comma-separated")
+                .takes_value(true)
+                .default_value("txt")
+                .use_delimiter(true)
+                .require_delimiter(true)
+                .value_delimiter(','),
         )
         .arg(
-            Arg::with_name("check_current")
-                .short('c')
-                .long("check-current")
-                .help("Only include episodes published within the last 30 days in newest output")
+            Arg::with_name("all_files_format")
+                .short('F')
+                .long("all-format")
+                .help("Format for all_files output (txt, md, html, player)")
                 .takes_value(true)
-                .default_value("true"),
+                .default_value("txt")
+                .possible_values(["txt", "md", "html", "player"]),
         )
         .arg(
-            Arg::with_name("filter_all")
-                .short('a')
-                .long("filter-all")
-                .help("Apply time filter to all_files output as well (not just newest)")
-                .takes_value(true)
-                .default_value("false"),
+            Arg::with_name("out_dir")
+                .short('O')
+                .long("out-dir")
+                .help("Directory for output files, created if missing (default: next to the OPML file)")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("days")
-                .short('d')
-                .long("days")
-                .help("Number of days to consider as current")
+            Arg::with_name("all_name")
+                .long("all-name")
+                .help("Filename pattern for all_files output, or - for stdout ({name}, {fmt}, {date}, {date:%Y%m%d})")
                 .takes_value(true)
-                .default_value("30"),
+                .default_value("{name}.{fmt}"),
         )
         .arg(
-            Arg::with_name("chronological")
-                .short('o')
-                .long("chronological")
-                .help("Sort all episodes chronologically (oldest first) in the output file")
+            Arg::with_name("newest_name")
+                .long("newest-name")
+                .help("Filename pattern for newest output, or - for stdout ({name}, {fmt}, {date}, {date:%Y%m%d})")
                 .takes_value(true)
-                .default_value("false"),
+                .default_value("newest.{fmt}"),
         )
         .arg(
-            Arg::with_name("formats")
-                .short('f')
-                .long("formats")
-                .help("Format(s) for output files (txt, md, html), comma-separated")
-                .takes_value(true)
-                .default_value("txt")
-                .use_delimiter(true)
-                .require_delimiter(true)
-                .value_delimiter(','),
+            Arg::with_name("since_last_run")
+                .long("since-last-run")
+                .help("Also write episodes not seen in the previous run to new.<fmt> (requires --db)")
+                .requires("db"),
         )
         .arg(
-            Arg::with_name("all_files_format")
-                .short('F')
-                .long("all-format")
-                .help("Format for all_files output (txt, md, html)")
+            Arg::with_name("new_name")
+                .long("new-name")
+                .help("Filename pattern for the --since-last-run output, or - for stdout")
                 .takes_value(true)
-                .default_value("txt")
-                .possible_values(&["txt", "md", "html"]),
+                .default_value("new.{fmt}"),
+        )
+        .subcommand(
+            App::new("download")
+                .about("Downloads selected episodes to disk")
+                .args(collection_args())
+                .arg(
+                    Arg::with_name("select")
+                        .short('s')
+                        .long("select")
+                        .help("Which episodes to download: the newest per feed, those new since the last run (requires --db), or all")
+                        .takes_value(true)
+                        .default_value("newest")
+                        .possible_values(["newest", "new", "all"])
+                        .requires_if("new", "db"),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .help("Directory to download episodes into")
+                        .takes_value(true)
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("name_template")
+                        .short('t')
+                        .long("name-template")
+                        .help("Path template under --dir ({group}, {feed}, {title}, {date}, {date:%Y%m%d}, {ext}, {id})")
+                        .takes_value(true)
+                        .default_value(naming::DEFAULT_TEMPLATE),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .help("Number of episodes to download in parallel")
+                        .takes_value(true)
+                        .default_value("4"),
+                )
+                .arg(
+                    Arg::with_name("no_tags")
+                        .long("no-tags")
+                        .help("Do not write ID3/MP4 tags from the feed metadata into downloaded files"),
+                ),
+        )
+        .subcommand(
+            App::new("check")
+                .about("Checks that the media URLs of the selected episodes work")
+                .args(collection_args())
+                .arg(
+                    Arg::with_name("select")
+                        .short('s')
+                        .long("select")
+                        .help("Which episodes to check: the newest per feed, those new since the last run (requires --db), or all")
+                        .takes_value(true)
+                        .default_value("newest")
+                        .possible_values(["newest", "new", "all"])
+                        .requires_if("new", "db"),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .help("Number of URLs to check in parallel")
+                        .takes_value(true)
+                        .default_value("8"),
+                ),
+        )
+        .subcommand(
+            App::new("stats")
+                .about("Summarizes how often each subscription publishes and flags abandoned feeds")
+                .args(collection_args())
+                .arg(
+                    Arg::with_name("format")
+                        .short('f')
+                        .long("format")
+                        .help("Report format")
+                        .takes_value(true)
+                        .default_value("txt")
+                        .possible_values(["txt", "md", "html", "json"]),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("File to write the report to, or - for stdout ({name}, {fmt}, {date}, {date:%Y%m%d})")
+                        .takes_value(true)
+                        .default_value("-"),
+                )
+                .arg(
+                    Arg::with_name("abandoned_days")
+                        .long("abandoned-days")
+                        .help("Flag feeds with no episode in this many days as abandoned")
+                        .takes_value(true)
+                        .default_value("180"),
+                ),
+        )
+        .subcommand(
+            App::new("merge")
+                .about("Reads earlier text outputs back, merges them and filters them again")
+                .args(filter_args())
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .help("Only keep episodes published since this date or age")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .help("Only keep episodes published before this date or age")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short('f')
+                        .long("format")
+                        .help("Format of the merged output")
+                        .takes_value(true)
+                        .default_value("txt")
+                        .possible_values(["txt", "md", "html", "player"]),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("File to write the merged episodes to, or - for stdout ({fmt}, {date}, {date:%Y%m%d})")
+                        .takes_value(true)
+                        .default_value("-"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .help("Text outputs of earlier runs")
+                        .required(true)
+                        .multiple_values(true),
+                ),
         )
-        .get_matches();
+        .subcommand(
+            App::new("diff")
+                .about("Lists the episodes and feeds added and removed between two text outputs or two stored runs")
+                .arg(
+                    db_arg()
+                        .help("Compare two runs stored in this episode database instead of two files")
+                        .conflicts_with("files"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("Run to compare from, by id (default: the run before --to)")
+                        .takes_value(true)
+                        .requires("db"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .help("Run to compare to, by id (default: the latest run)")
+                        .takes_value(true)
+                        .requires("db"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short('f')
+                        .long("format")
+                        .help("Report format")
+                        .takes_value(true)
+                        .default_value("txt")
+                        .possible_values(["txt", "md", "json"]),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("File to write the report to, or - for stdout ({fmt}, {date}, {date:%Y%m%d})")
+                        .takes_value(true)
+                        .default_value("-"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .help("Older and newer text output, e.g. yesterday's and today's newest.txt")
+                        .number_of_values(2)
+                        .required_unless_present("db"),
+                ),
+        )
+        .subcommand(
+            App::new("prune")
+                .about("Deletes downloaded episodes that fall outside the retention rules")
+                .arg(db_arg().required(true))
+                .arg(
+                    Arg::with_name("keep_last")
+                        .long("keep-last")
+                        .help("Keep only the newest N episodes of each feed")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("keep_days")
+                        .long("keep-days")
+                        .help("Keep only episodes published within the last N days")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max_gb")
+                        .long("max-gb")
+                        .help("Keep the whole library under N GB, deleting the oldest episodes first")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("feed_rule")
+                        .long("feed-rule")
+                        .help("Rules for one feed, by name or URL, e.g. 'Feed Name:last=5,days=30,gb=2'")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .short('n')
+                        .long("dry-run")
+                        .help("Only list what would be deleted"),
+                ),
+        )
+        .subcommand(
+            App::new("keep")
+                .about("Marks downloaded episodes as kept, so prune never deletes them")
+                .arg(db_arg().required(true))
+                .arg(
+                    Arg::with_name("undo")
+                        .long("undo")
+                        .help("Remove the mark instead, so the files are pruned as usual"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .help("Downloaded files to mark")
+                        .required(true)
+                        .multiple_values(true),
+                ),
+        )
+        .subcommand(
+            App::new("mark-played")
+                .about("Records playback state: played, position or starred")
+                .arg(db_arg().required(true))
+                .arg(
+                    Arg::with_name("unplayed")
+                        .long("unplayed")
+                        .help("Mark as not played instead"),
+                )
+                .arg(
+                    Arg::with_name("position")
+                        .long("position")
+                        .help("Mark as in progress at this position (seconds, MM:SS or H:MM:SS)")
+                        .takes_value(true)
+                        .conflicts_with("unplayed"),
+                )
+                .arg(
+                    Arg::with_name("star")
+                        .long("star")
+                        .help("Star the episodes"),
+                )
+                .arg(
+                    Arg::with_name("unstar")
+                        .long("unstar")
+                        .help("Remove the star")
+                        .conflicts_with("star"),
+                )
+                .arg(
+                    Arg::with_name("episodes")
+                        .help("Episodes by media URL, guid or downloaded file")
+                        .required(true)
+                        .multiple_values(true),
+                ),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("Serves an HTTP endpoint the player can post playback state to")
+                .arg(db_arg().required(true))
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .help("Address to listen on")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:8642"),
+                )
+                .arg(
+                    Arg::with_name("allow_origin")
+                        .long("allow-origin")
+                        .help("Also answer browser pages from this origin, e.g. http://localhost:8000")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::with_name("allow_file_pages")
+                        .long("allow-file-pages")
+                        .help("Also answer pages opened from disk (the null origin); sandboxed frames on any site send it too"),
+                ),
+        )
+}
+
+// Arguments shared by every command that fetches the subscriptions
+fn collection_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::with_name("opml_file")
+            .help("OPML files containing RSS feeds, or directories of them; feeds listed in several are fetched once")
+            .required(true)
+            .multiple_values(true)
+            .index(1),
+        Arg::with_name("since")
+            .long("since")
+            .help("Newest output: only episodes published since this date or age (2025-01-01, 30d, 2w, 6mo, 1y; all for no bound)")
+            .takes_value(true)
+            .default_value("30d"),
+        Arg::with_name("until")
+            .long("until")
+            .help("Newest output: only episodes published before this date or age (a bare date includes that day)")
+            .takes_value(true),
+        Arg::with_name("all_since")
+            .long("all-since")
+            .help("All episodes output: only episodes published since this date or age")
+            .takes_value(true),
+        Arg::with_name("all_until")
+            .long("all-until")
+            .help("All episodes output: only episodes published before this date or age")
+            .takes_value(true),
+        Arg::with_name("chronological")
+            .short('o')
+            .long("chronological")
+            .help("Sort all episodes chronologically (oldest first) in the output file")
+            .takes_value(true)
+            .default_value("false"),
+        db_arg(),
+        Arg::with_name("include_played")
+            .long("include-played")
+            .help("Keep episodes marked played in the newest and new outputs (with --db)"),
+        Arg::with_name("dedup")
+            .long("dedup")
+            .help("Drop episodes that an earlier feed in the OPML file also carries (same guid, media URL, or title and date)"),
+        Arg::with_name("strip_trackers")
+            .long("strip-trackers")
+            .help("Unwrap analytics redirects (Podtrac, Chartable, Podscribe, ...) from media URLs"),
+        Arg::with_name("tracker_rule")
+            .long("tracker-rule")
+            .help("Another redirect to unwrap, as a prefix like track.example.com/r/*/ where * stands for an id")
+            .takes_value(true)
+            .multiple_occurrences(true),
+        Arg::with_name("check_stripped")
+            .long("check-stripped")
+            .help("Keep an unwrapped URL only if the media host answers a HEAD request")
+            .requires("strip_trackers"),
+        Arg::with_name("covers")
+            .long("covers")
+            .help("Download, shrink and cache episode artwork in this directory, and point the outputs at the cached files")
+            .takes_value(true),
+        Arg::with_name("cover_size")
+            .long("cover-size")
+            .help("Largest width and height of cached covers, in pixels")
+            .takes_value(true)
+            .default_value("600"),
+        Arg::with_name("undated")
+            .long("undated")
+            .help("Undated episodes: keep (as the oldest), drop, or put them first or last in every list")
+            .takes_value(true)
+            .default_value("keep")
+            .possible_values(["keep", "drop", "first", "last"]),
+        Arg::with_name("infer_dates")
+            .long("infer-dates")
+            .help("Date undated episodes from these sources, in order: last-modified, feed-order, first-seen (with --db)")
+            .takes_value(true),
+        Arg::with_name("per_feed")
+            .long("per-feed")
+            .help("Number of newest episodes per feed in the newest output (0 for all)")
+            .takes_value(true)
+            .default_value("1"),
+        Arg::with_name("sample")
+            .long("sample")
+            .help("Pick N episodes at random for the newest output, from those --per-feed leaves")
+            .takes_value(true),
+        Arg::with_name("seed")
+            .long("seed")
+            .help("Seed for --sample, to repeat a previous pick")
+            .takes_value(true)
+            .requires("sample"),
+        Arg::with_name("max_total")
+            .long("max-total")
+            .help("At most N episodes in the newest output, keeping the newest")
+            .takes_value(true),
+        Arg::with_name("budget")
+            .long("budget")
+            .help("Fill this much listening time (3h, 1h30m) with the newest episodes, taking feeds in turn; implies --per-feed 0 unless given")
+            .takes_value(true),
+    ]
+    .into_iter()
+    .chain(filter_args())
+    .collect()
+}
+
+// Episode filters, shared with the commands that read listings back
+fn filter_args() -> Vec<Arg<'static>> {
+    vec![
+        filter_arg("include_title", "include-title", "Only keep episodes whose title matches this regex"),
+        filter_arg("exclude_title", "exclude-title", "Drop episodes whose title matches this regex"),
+        filter_arg("include_feed", "include-feed", "Only keep feeds whose name matches this regex"),
+        filter_arg("exclude_feed", "exclude-feed", "Drop feeds whose name matches this regex"),
+        filter_arg("include_description", "include-description", "Only keep episodes whose description contains this keyword"),
+        filter_arg("exclude_description", "exclude-description", "Drop episodes whose description contains this keyword"),
+        Arg::with_name("min_duration")
+            .long("min-duration")
+            .help("Drop episodes shorter than this (45m, 1h30m, 1:30:00); episodes of unknown length are kept")
+            .takes_value(true),
+        Arg::with_name("max_duration")
+            .long("max-duration")
+            .help("Drop episodes longer than this; episodes of unknown length are kept")
+            .takes_value(true),
+        Arg::with_name("in_group")
+            .long("in-group")
+            .help("Apply the filter options that follow only to feeds in this OPML folder")
+            .takes_value(true)
+            .multiple_occurrences(true),
+    ]
+}
+
+fn filter_arg(name: &'static str, long: &'static str, help: &'static str) -> Arg<'static> {
+    Arg::with_name(name)
+        .long(long)
+        .help(help)
+        .takes_value(true)
+        .multiple_occurrences(true)
+}
+
+const FILTER_ARGS: &[(&str, FilterKind)] = &[
+    ("include_title", FilterKind::IncludeTitle),
+    ("exclude_title", FilterKind::ExcludeTitle),
+    ("include_feed", FilterKind::IncludeFeed),
+    ("exclude_feed", FilterKind::ExcludeFeed),
+    ("include_description", FilterKind::IncludeDescription),
+    ("exclude_description", FilterKind::ExcludeDescription),
+];
+
+fn newest_selection(matches: &ArgMatches) -> Result<Selection> {
+    let count = |name: &str, flag: &str| -> Result<Option<usize>> {
+        matches.value_of(name)
+            .map(|value| {
+                value.parse::<usize>().map_err(|_| {
+                    Error::InvalidArgument(format!("{} expects a number, got '{}'", flag, value))
+                })
+            })
+            .transpose()
+    };
+    let sample = count("sample", "--sample")?;
+    let seed = match matches.value_of("seed") {
+        Some(value) => value
+            .parse::<u64>()
+            .map_err(|_| Error::InvalidArgument(format!("--seed expects a number, got '{}'", value)))?,
+        None => Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+    };
+    if sample.is_some() {
+        eprintln!("Sampling with --seed {}", seed);
+    }
+    let budget = matches.value_of("budget")
+        .map(|value| duration::parse_arg("--budget", value))
+        .transpose()?;
+    // A budget is filled from each feed's back catalogue unless the per-feed limit is set explicitly
+    let per_feed = match count("per_feed", "--per-feed")? {
+        Some(_) if budget.is_some() && matches.occurrences_of("per_feed") == 0 => 0,
+        per_feed => per_feed.unwrap_or(1),
+    };
+    Ok(Selection {
+        per_feed,
+        sample,
+        seed,
+        max_total: count("max_total", "--max-total")?,
+        budget,
+    })
+}
+
+// Filter options belong to the closest --in-group before them on the command line,
+// or apply to every feed when there is none
+fn episode_filters(matches: &ArgMatches) -> Result<Filters> {
+    let positioned = |name: &str| -> Vec<(usize, String)> {
+        match (matches.indices_of(name), matches.values_of(name)) {
+            (Some(indices), Some(values)) => indices.zip(values.map(str::to_string)).collect(),
+            _ => Vec::new(),
+        }
+    };
+    let groups = positioned("in_group");
+
+    let mut filters = Filters::default();
+    for (name, kind) in FILTER_ARGS {
+        for (index, pattern) in positioned(name) {
+            let scope = groups.iter().filter(|(at, _)| *at < index).max_by_key(|(at, _)| *at);
+            let set = match scope {
+                None => &mut filters.global,
+                Some((_, group)) => match filters.groups.iter().position(|(g, _)| g == group) {
+                    Some(i) => &mut filters.groups[i].1,
+                    None => {
+                        filters.groups.push((group.clone(), FilterSet::default()));
+                        &mut filters.groups.last_mut().unwrap().1
+                    }
+                },
+            };
+            set.add(*kind, &pattern)?;
+        }
+    }
+    let bound = |name: &str, flag: &str| matches.value_of(name).map(|value| duration::parse_arg(flag, value)).transpose();
+    filters.min_duration = bound("min_duration", "--min-duration")?;
+    filters.max_duration = bound("max_duration", "--max-duration")?;
+    Ok(filters)
+}
+
+fn jobs_arg(matches: &ArgMatches) -> Result<usize> {
+    let jobs = matches.value_of("jobs").unwrap();
+    jobs.parse::<usize>()
+        .ok()
+        .filter(|&jobs| jobs > 0)
+        .ok_or_else(|| Error::InvalidArgument(format!("--jobs expects a positive number, got '{}'", jobs)))
+}
+
+fn db_arg() -> Arg<'static> {
+    Arg::with_name("db")
+        .long("db")
+        .help("SQLite episode database to keep history across runs; outputs are rendered from it")
+        .takes_value(true)
+}
+
+/// Episodes gathered from one fetch of every subscription.
+struct Collection {
+    all: Vec<Episode>,
+    newest: Vec<Episode>,
+    /// Only filled in when episodes new since the last run were asked for
+    new: Vec<Episode>,
+    /// Every episode with its metadata, before the time windows: the database
+    /// history with `--db`, otherwise what the feeds list now
+    known: Vec<Episode>,
+    /// The subscriptions that could be fetched, in OPML order
+    feeds: Vec<Subscription>,
+    failed_feeds: usize,
+    total_feeds: usize,
+    /// Holds the run recorded with --db until `keep_run`
+    store: Option<Store>,
+}
+
+impl Collection {
+    // The run is only kept once the command did its job, so a failed run leaves the
+    // history as it was
+    fn keep_run(&self) -> Result<()> {
+        match &self.store {
+            Some(store) => store.keep_run(),
+            None => Ok(()),
+        }
+    }
+
+    // Runs that lost some feeds still succeed at their job, but exit with a partial-failure code
+    fn finish(&self) -> Result<()> {
+        if self.failed_feeds > 0 {
+            return Err(Error::PartialFeedFailures { failed: self.failed_feeds, total: self.total_feeds });
+        }
+        Ok(())
+    }
+}
 
+async fn run_outputs(matches: &ArgMatches) -> Result<()> {
     let opml_path = PathBuf::from(matches.value_of("opml_file").unwrap());
     
     // Parse formats for newest files
     let formats: Vec<&str> = matches.values_of("formats").unwrap_or_default().collect();
     
-    // Get format for all_files
+    // Resolve output locations
+    let out_dir = matches.value_of("out_dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| default_out_dir(&opml_path));
+    let opml_name = opml_path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "powercrust".to_string());
+    let now = Utc::now();
+
     let all_files_format = matches.value_of("all_files_format").unwrap_or("txt");
-    let output_txt = opml_path.with_extension(all_files_format);
-    
-    let check_current = matches.value_of("check_current")
-        .unwrap_or("true")
-        .to_lowercase() == "true";
-    let filter_all = matches.value_of("filter_all")
-        .unwrap_or("false")
-        .to_lowercase() == "true";
-    let current_days = matches
-        .value_of("days")
-        .unwrap()
+    let all_target = OutputTarget::resolve(
+        matches.value_of("all_name").unwrap(),
+        &out_dir,
+        &opml_name,
+        all_files_format,
+        now,
+    )?;
+    let newest_targets: Vec<(&str, OutputTarget)> = formats
+        .iter()
+        .map(|format| {
+            let target = OutputTarget::resolve(
+                matches.value_of("newest_name").unwrap(),
+                &out_dir,
+                &opml_name,
+                format,
+                now,
+            )?;
+            Ok((*format, target))
+        })
+        .collect::<Result<_>>()?;
+    let since_last_run = matches.is_present("since_last_run");
+    let new_targets: Vec<(&str, OutputTarget)> = if since_last_run {
+        formats
+            .iter()
+            .map(|format| {
+                let target = OutputTarget::resolve(
+                    matches.value_of("new_name").unwrap(),
+                    &out_dir,
+                    &opml_name,
+                    format,
+                    now,
+                )?;
+                Ok((*format, target))
+            })
+            .collect::<Result<_>>()?
+    } else {
+        Vec::new()
+    };
+
+    // Create the output folders up front, so a missing --out-dir does not waste a fetch
+    let targets = std::iter::once(&all_target).chain(newest_targets.iter().chain(&new_targets).map(|(_, target)| target));
+    for target in targets {
+        if let OutputTarget::File(path) = target {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+            }
+        }
+    }
+
+    let collection = collect(matches, now, since_last_run, true).await?;
+
+    eprintln!("Found {} episodes.", collection.all.len());
+
+    // Write all episodes in the requested format, then newest episodes in each requested format
+    let mut staged = StagedOutputs::default();
+    staged.write_episodes(
+        &all_target,
+        &collection.all,
+        all_files_format,
+        "All Podcast Episodes"
+    )?;
+    for (format, target) in &newest_targets {
+        staged.write_episodes(
+            target,
+            &collection.newest, 
+            format, 
+            "Newest Podcast Episodes"
+        )?;
+    }
+    for (format, target) in &new_targets {
+        staged.write_episodes(
+            target,
+            &collection.new,
+            format,
+            "New Podcast Episodes"
+        )?;
+    }
+    staged.commit()?;
+    collection.keep_run()?;
+
+    eprintln!("Done. All episodes written to {}.", all_target.display());
+    for (_, target) in &newest_targets {
+        eprintln!("Newest episodes written to {}.", target.display());
+    }
+    for (_, target) in &new_targets {
+        eprintln!("{} new episodes written to {}.", collection.new.len(), target.display());
+    }
+
+    collection.finish()
+}
+
+async fn run_download(matches: &ArgMatches) -> Result<()> {
+    let select = matches.value_of("select").unwrap();
+    let dir = PathBuf::from(matches.value_of("dir").unwrap());
+    let jobs = jobs_arg(matches)?;
+    naming::check_template(matches.value_of("name_template").unwrap())?;
+
+    let collection = collect(matches, Utc::now(), select == "new", true).await?;
+    let episodes = match select {
+        "all" => collection.all.clone(),
+        "new" => collection.new.clone(),
+        _ => collection.newest.clone(),
+    };
+    eprintln!("Downloading {} episodes to {}", episodes.len(), dir.display());
+
+    // Media files can take far longer than a feed, so only connecting is bounded here;
+    // a transfer that stalls is cut off by the download's idle timeout
+    let client = http_client(|builder| builder.connect_timeout(std::time::Duration::from_secs(15)))?;
+
+    // Recorded downloads are stored by canonical path
+    let owners = match &collection.store {
+        Some(store) => store.download_owners()?,
+        None => HashMap::new(),
+    };
+    let owner = |path: &Path| path.canonicalize().ok().and_then(|path| owners.get(&path).cloned());
+    let paths = naming::plan_paths(&episodes, matches.value_of("name_template").unwrap(), &dir, owner);
+    // The run is kept before downloading, so serve and mark-played are not locked out
+    // for as long as the downloads take; each finished download is recorded on its own
+    collection.keep_run()?;
+    let options = DownloadOptions { jobs, write_tags: !matches.is_present("no_tags") };
+    let results = download_episodes(&client, episodes.into_iter().zip(paths).collect(), &options).await;
+
+    // Files are tracked in the database so prune can apply retention rules to them later
+    let total = results.len();
+    let mut failed = 0;
+    for (episode, result) in results {
+        let path = match result {
+            Ok(Outcome::Downloaded(path)) => {
+                eprintln!("Downloaded {}", path.display());
+                path
+            }
+            Ok(Outcome::AlreadyPresent(path)) => {
+                eprintln!("Already downloaded {}", path.display());
+                path
+            }
+            Err(e) => {
+                eprintln!("Warning: {}: {}", episode.title, e);
+                failed += 1;
+                continue;
+            }
+        };
+        if let Some(store) = &collection.store {
+            let path = path.canonicalize().map_err(Error::io(&path))?;
+            let library = dir.canonicalize().map_err(Error::io(&dir))?;
+            store.record_download(&episode, &path, &library, Utc::now())?;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::DownloadFailures { failed, total });
+    }
+    collection.finish()
+}
+
+fn run_prune(matches: &ArgMatches) -> Result<()> {
+    let rules = prune_rules(matches)?;
+    let dry_run = matches.is_present("dry_run");
+    let store = Store::open(Path::new(matches.value_of("db").unwrap()))?;
+
+    // Files removed by hand are simply forgotten
+    let downloads = store.downloads()?;
+    let mut present = Vec::new();
+    for download in &downloads {
+        match std::fs::metadata(&download.path) {
+            Ok(meta) => present.push((download, meta.len())),
+            Err(_) if dry_run => {}
+            Err(_) => store.forget_download(&download.path)?,
+        }
+    }
+    let library_size: u64 = present.iter().map(|(_, size)| size).sum();
+    eprintln!("Library: {} files, {}", present.len(), retention::format_size(library_size));
+
+    let expired = retention::expired(&present, &rules, Utc::now());
+    let total = expired.len();
+    let mut failed = 0;
+    let mut freed = 0;
+    for file in expired {
+        let path = &file.download.path;
+        if dry_run {
+            eprintln!("Would delete {} ({})", path.display(), file.reason);
+            freed += file.size;
+            continue;
+        }
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                eprintln!("Deleted {} ({})", path.display(), file.reason);
+                store.forget_download(path)?;
+                freed += file.size;
+                if let Some(library) = &file.download.library {
+                    retention::remove_empty_dirs(path, library);
+                }
+            }
+            Err(e) => {
+                eprintln!("Warning: {}", Error::io(path)(e));
+                failed += 1;
+            }
+        }
+    }
+    eprintln!(
+        "{} {} files, {}",
+        if dry_run { "Would prune" } else { "Pruned" },
+        total - failed,
+        retention::format_size(freed)
+    );
+
+    if failed > 0 {
+        return Err(Error::PruneFailures { failed, total });
+    }
+    Ok(())
+}
+
+fn prune_rules(matches: &ArgMatches) -> Result<Rules> {
+    let number = |name: &str, flag: &str| -> Result<Option<u64>> {
+        matches.value_of(name)
+            .map(|value| {
+                value.parse::<u64>().map_err(|_| {
+                    Error::InvalidArgument(format!("{} expects a number, got '{}'", flag, value))
+                })
+            })
+            .transpose()
+    };
+    let max_bytes = matches.value_of("max_gb")
+        .map(|value| {
+            retention::parse_gb(value).ok_or_else(|| {
+                Error::InvalidArgument(format!("--max-gb expects a number of GB, got '{}'", value))
+            })
+        })
+        .transpose()?;
+    let global = Policy {
+        last: number("keep_last", "--keep-last")?.map(|n| n as usize),
+        days: number("keep_days", "--keep-days")?.map(|n| i64::try_from(n).unwrap_or(i64::MAX)),
+        max_bytes,
+    };
+
+    let mut feeds = HashMap::new();
+    for rule in matches.values_of("feed_rule").unwrap_or_default() {
+        let (feed, spec) = rule.rsplit_once(':').ok_or_else(|| {
+            Error::InvalidArgument(format!("--feed-rule expects 'Feed Name:last=N,days=N,gb=N', got '{}'", rule))
+        })?;
+        feeds.insert(feed.trim().to_string(), Policy::parse(spec)?);
+    }
+
+    if global.last.is_none() && global.days.is_none() && global.max_bytes.is_none() && feeds.is_empty() {
+        return Err(Error::InvalidArgument(
+            "prune needs at least one of --keep-last, --keep-days, --max-gb or --feed-rule".to_string(),
+        ));
+    }
+    Ok(Rules { global, feeds })
+}
+
+fn run_keep(matches: &ArgMatches) -> Result<()> {
+    let store = Store::open(Path::new(matches.value_of("db").unwrap()))?;
+    let kept = !matches.is_present("undo");
+    for file in matches.values_of("files").unwrap_or_default() {
+        let path = Path::new(file);
+        let path = path.canonicalize().map_err(Error::io(path))?;
+        if store.set_kept(&path, kept)? {
+            eprintln!("{} {}", if kept { "Keeping" } else { "No longer keeping" }, path.display());
+        } else {
+            eprintln!("Warning: {} is not a recorded download", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn run_mark_played(matches: &ArgMatches) -> Result<()> {
+    let store = Store::open(Path::new(matches.value_of("db").unwrap()))?;
+    let position = matches.value_of("position")
+        .map(|value| {
+            parse_position(value).ok_or_else(|| {
+                Error::InvalidArgument(format!("--position expects seconds, MM:SS or H:MM:SS, got '{}'", value))
+            })
+        })
+        .transpose()?;
+    let starred = if matches.is_present("star") {
+        Some(true)
+    } else if matches.is_present("unstar") {
+        Some(false)
+    } else {
+        None
+    };
+    // Plain `mark-played` marks played; starring alone leaves the played state alone
+    let played = if matches.is_present("unplayed") || position.is_some() {
+        Some(false)
+    } else if starred.is_none() {
+        Some(true)
+    } else {
+        None
+    };
+    let change = PlaybackChange { played, position, starred };
+
+    let now = Utc::now();
+    for episode in matches.values_of("episodes").unwrap_or_default() {
+        // Downloaded files are recorded by their absolute path
+        let key = match Path::new(episode).canonicalize() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => episode.to_string(),
+        };
+        if store.update_playback(&key, &change, now)? == 0 {
+            eprintln!("Warning: no known episode matches '{}'", episode);
+        } else {
+            eprintln!("Updated {}", episode);
+        }
+    }
+    Ok(())
+}
+
+// Seconds, MM:SS or H:MM:SS
+fn parse_position(value: &str) -> Option<f64> {
+    value.split(':').try_fold(0.0, |total, part| {
+        let part: f64 = part.trim().parse().ok()?;
+        (part >= 0.0).then_some(total * 60.0 + part)
+    })
+}
+
+async fn run_check(matches: &ArgMatches) -> Result<()> {
+    let select = matches.value_of("select").unwrap();
+    let jobs = jobs_arg(matches)?;
+    let now = Utc::now();
+
+    let collection = collect(matches, now, select == "new", false).await?;
+    let episodes = match select {
+        "all" => &collection.all,
+        "new" => &collection.new,
+        _ => &collection.newest,
+    };
+    eprintln!("Checking {} media URLs", episodes.len());
+
+    let client = http_client(|builder| builder.timeout(std::time::Duration::from_secs(30)))?;
+    let results = check::check_links(&client, episodes, jobs).await;
+
+    // Results go into the database, so later runs can flag broken episodes in their outputs
+    let store = matches.value_of("db")
+        .map(|path| Store::open(Path::new(path)))
+        .transpose()?;
+    let mut broken = 0;
+    let mut warned = 0;
+    for (episode, problems) in episodes.iter().zip(&results) {
+        let reasons = |broken: bool| {
+            problems.iter().filter(|p| p.is_broken() == broken).map(|p| p.to_string()).collect::<Vec<_>>()
+        };
+        let (fatal, warnings) = (reasons(true), reasons(false));
+        if !fatal.is_empty() {
+            broken += 1;
+        } else if !warnings.is_empty() {
+            warned += 1;
+        }
+        if !problems.is_empty() {
+            let all: Vec<String> = fatal.iter().chain(&warnings).cloned().collect();
+            println!("{}: {} - {}: {}", episode.feed_name, episode.title, episode.media_url, all.join("; "));
+        }
+        if let Some(store) = &store {
+            let problem = (!fatal.is_empty()).then(|| fatal.join("; "));
+            store.record_link_check(&episode.media_url, problem.as_deref(), now)?;
+        }
+    }
+    eprintln!(
+        "Checked {} media URLs: {} broken, {} with warnings",
+        episodes.len(),
+        broken,
+        warned
+    );
+
+    // Broken links decide the exit code, so feeds that could not be checked are reported here
+    if broken > 0 {
+        if let Err(e) = collection.finish() {
+            eprintln!("Warning: {}", e);
+        }
+        return Err(Error::BrokenLinks { broken, total: episodes.len() });
+    }
+    collection.finish()
+}
+
+async fn run_stats(matches: &ArgMatches) -> Result<()> {
+    let format = matches.value_of("format").unwrap();
+    let abandoned_arg = matches.value_of("abandoned_days").unwrap();
+    let abandoned_after = abandoned_arg
         .parse::<i64>()
-        .unwrap_or(30);
-    
+        .ok()
+        .and_then(chrono::Duration::try_days)
+        .ok_or_else(|| Error::InvalidArgument(format!("--abandoned-days expects a number, got '{}'", abandoned_arg)))?;
+    let now = Utc::now();
+    let opml_path = PathBuf::from(matches.value_of("opml_file").unwrap());
+    let opml_name = opml_path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "powercrust".to_string());
+    let target = OutputTarget::resolve(matches.value_of("output").unwrap(), Path::new("."), &opml_name, format, now)?;
+
+    let collection = collect(matches, now, false, false).await?;
+    let stats = stats::feed_stats(&collection.feeds, &collection.known, now, abandoned_after);
+    let abandoned = stats.iter().filter(|s| s.abandoned).count();
+
+    let mut staged = StagedOutputs::default();
+    staged.write(&target, |out| stats::render(out, &stats, format))?;
+    staged.commit()?;
+    eprintln!("{} feeds summarized, {} abandoned; report written to {}.", stats.len(), abandoned, target.display());
+
+    collection.finish()
+}
+
+fn run_merge(matches: &ArgMatches) -> Result<()> {
+    let format = matches.value_of("format").unwrap();
+    let now = Utc::now();
+    let window = Window::parse(("--since", matches.value_of("since")), ("--until", matches.value_of("until")), now)?;
+    let filters = episode_filters(matches)?;
+    let target = OutputTarget::resolve(matches.value_of("output").unwrap(), Path::new("."), "merged", format, now)?;
+
+    // The same media URL in several files is one episode; the file given last describes it
+    let mut episodes: Vec<Episode> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for file in matches.values_of("files").unwrap() {
+        let read = listing::read(Path::new(file))?;
+        eprintln!("Read {} episodes from {}", read.len(), file);
+        for episode in read {
+            match seen.get(&episode.media_url) {
+                Some(&i) => episodes[i] = episode,
+                None => {
+                    seen.insert(episode.media_url.clone(), episodes.len());
+                    episodes.push(episode);
+                }
+            }
+        }
+    }
+    let total = episodes.len();
+    episodes.retain(|episode| filters.matches(episode) && window.contains(episode));
+    Undated::Keep.sort(&mut episodes, true);
+
+    let mut staged = StagedOutputs::default();
+    staged.write_episodes(&target, &episodes, format, "Merged Podcast Episodes")?;
+    staged.commit()?;
+    eprintln!("{} of {} episodes written to {}.", episodes.len(), total, target.display());
+    Ok(())
+}
+
+fn run_diff(matches: &ArgMatches) -> Result<()> {
+    let format = matches.value_of("format").unwrap();
+    let now = Utc::now();
+    let target = OutputTarget::resolve(matches.value_of("output").unwrap(), Path::new("."), "diff", format, now)?;
+
+    let (from, to, old, new) = match matches.value_of("db") {
+        Some(db) => {
+            let store = Store::open(Path::new(db))?;
+            let runs = store.runs()?;
+            let run_arg = |name: &str, flag: &str| -> Result<Option<usize>> {
+                let Some(value) = matches.value_of(name) else { return Ok(None) };
+                value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|id| runs.iter().position(|(run, _)| *run == id))
+                    .map(Some)
+                    .ok_or_else(|| Error::InvalidArgument(format!("{} expects the id of a stored run, got '{}'", flag, value)))
+            };
+            let to = match run_arg("to", "--to")? {
+                Some(to) => to,
+                None => runs.len().checked_sub(1).ok_or_else(|| Error::InvalidArgument(format!("{} has no stored runs", db)))?,
+            };
+            let from = match run_arg("from", "--from")? {
+                Some(from) => from,
+                None => to.checked_sub(1).ok_or_else(|| Error::InvalidArgument("there is no run before the one to compare to".to_string()))?,
+            };
+            let label = |(id, started_at): (i64, DateTime<Utc>)| format!("run {} ({})", id, started_at.format("%Y-%m-%d %H:%M"));
+            (label(runs[from]), label(runs[to]), store.run_episodes(runs[from].0)?, store.run_episodes(runs[to].0)?)
+        }
+        None => {
+            let files: Vec<&str> = matches.values_of("files").unwrap().collect();
+            (
+                files[0].to_string(),
+                files[1].to_string(),
+                listing::read(Path::new(files[0]))?,
+                listing::read(Path::new(files[1]))?,
+            )
+        }
+    };
+
+    let diff = diff::diff(&old, &new);
+    let mut staged = StagedOutputs::default();
+    staged.write(&target, |out| diff::render(out, &diff, &from, &to, format))?;
+    staged.commit()?;
+    eprintln!(
+        "{} episodes added, {} removed; {} feeds appeared, {} vanished. Written to {}.",
+        diff.added(),
+        diff.removed(),
+        diff.appeared.len(),
+        diff.vanished.len(),
+        target.display()
+    );
+    Ok(())
+}
+
+async fn run_serve(matches: &ArgMatches) -> Result<()> {
+    let listen = matches.value_of("listen").unwrap();
+    let addr = listen
+        .parse()
+        .map_err(|_| Error::InvalidArgument(format!("--listen expects an address like 127.0.0.1:8642, got '{}'", listen)))?;
+    let store = Store::open(Path::new(matches.value_of("db").unwrap()))?;
+    let mut origins: Vec<String> = matches.values_of("allow_origin").into_iter().flatten().map(str::to_string).collect();
+    if matches.is_present("allow_file_pages") {
+        origins.push("null".to_string());
+    }
+    serve::serve(addr, store, origins).await
+}
+
+// The client for every request powercrust makes, with the timeouts `configure` sets.
+// It identifies as a desktop browser, since some hosts turn away unknown clients
+fn http_client(configure: impl FnOnce(ClientBuilder) -> ClientBuilder) -> Result<Client> {
+    configure(Client::builder().user_agent("Mozilla/5.0 (X11; Linux x86_64)"))
+        .build()
+        .map_err(Error::Client)
+}
+
+// Fetch every subscription in the OPML file and gather its episodes. Only commands that
+// act on the episodes `record` the run with --db; the others read the history without
+// moving what later runs count as new
+async fn collect(matches: &ArgMatches, now: DateTime<Utc>, want_new: bool, record: bool) -> Result<Collection> {
+    let opml_paths = opml_files(matches)?;
+
+    let newest_window = Window::parse(
+        ("--since", matches.value_of("since")),
+        ("--until", matches.value_of("until")),
+        now,
+    )?;
+    let all_window = Window::parse(
+        ("--all-since", matches.value_of("all_since")),
+        ("--all-until", matches.value_of("all_until")),
+        now,
+    )?;
+
     let chronological = matches.value_of("chronological")
         .unwrap_or("false")
         .to_lowercase() == "true";
 
-    let feeds = parse_opml(&opml_path);
-    println!("Found {} feeds", feeds.len());
+    let filters = episode_filters(matches)?;
+    let selection = newest_selection(matches)?;
+    let undated = Undated::parse(matches.value_of("undated").unwrap())?;
+    let date_sources = matches.value_of("infer_dates")
+        .map(DateSource::parse_list)
+        .transpose()?
+        .unwrap_or_default();
 
-    let client = Arc::new(Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64)")
-        .timeout(std::time::Duration::from_secs(15))
-        .build()
-        .unwrap());
+    let mut trackers = TrackerRules::builtin();
+    for rule in matches.values_of("tracker_rule").into_iter().flatten() {
+        trackers.add(rule)?;
+    }
+
+    let mut store = matches.value_of("db")
+        .map(|path| Store::open(Path::new(path)))
+        .transpose()?;
+
+    // Status goes to stderr so outputs can be piped from stdout
+    let mut lists = Vec::new();
+    for (i, path) in opml_paths.iter().enumerate() {
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        lists.push((name, parse_opml(path, i == 0)?));
+    }
+    let listed: usize = lists.iter().map(|(_, feeds)| feeds.len()).sum();
+    let feeds = feed::merge_subscriptions(lists);
+    if opml_paths.len() > 1 {
+        eprintln!("Found {} feeds in {} OPML files ({} listed more than once)", feeds.len(), opml_paths.len(), listed - feeds.len());
+    } else {
+        eprintln!("Found {} feeds", feeds.len());
+    }
+    if feeds.is_empty() {
+        let inputs: Vec<&str> = matches.values_of("opml_file").unwrap().collect();
+        return Err(Error::NoFeeds(inputs.join(", ")));
+    }
+    let total_feeds = feeds.len();
+
+    let client = Arc::new(http_client(|builder| builder.timeout(std::time::Duration::from_secs(15)))?);
 
     let sem = Arc::new(TokioSemaphore::new(20)); // Limit concurrent HTTP requests
     let fetches = join_all(
-        feeds.into_iter().map(|(name, url)| {
+        feeds.into_iter().map(|subscription| {
             let client = Arc::clone(&client);
             let sem = Arc::clone(&sem);
             tokio::spawn(async move {
                 let _permit = sem.acquire().await.unwrap();
-                fetch_feed(&client, &name, &url).await
+                fetch_feed(&client, subscription).await
             })
         }),
     )
     .await;
 
-    // Collect raw XML results for backward compatibility
-    let raw_results: Vec<(String, String)> = fetches
-        .into_iter()
-        .filter_map(|res| res.ok().flatten())
-        .collect();
+    // Collect raw XML results, reporting feeds that could not be fetched
+    let mut fetched_feeds: Vec<FetchedFeed> = Vec::new();
+    let mut failed_feeds = 0;
+    for res in fetches {
+        match res {
+            Ok(Ok(feed)) => fetched_feeds.push(feed),
+            Ok(Err(e)) => {
+                eprintln!("Warning: {}", e);
+                failed_feeds += 1;
+            }
+            Err(e) => {
+                eprintln!("Warning: feed task failed: {}", e);
+                failed_feeds += 1;
+            }
+        }
+    }
+    if fetched_feeds.is_empty() {
+        return Err(Error::AllFeedsFailed(total_feeds));
+    }
 
     // Extract all episodes with dates for chronological sorting (if enabled)
     let media_regex = Regex::new(r#""(http\S+?\.(mp3|mp4))["?]"#).unwrap();
     
-    // Process all episodes - handle the two different approaches based on chronological flag
-    if chronological {
-        // Process feeds using structured approach with proper date filtering
-        let mut all_episodes: Vec<Episode> = Vec::new();
-        
-        for (feed_name, content) in &raw_results {
-            if let Ok(channel) = Channel::read_from(content.as_bytes()) {
-                for item in channel.items() {
-// --- Inside your for item in channel.items() loop ---
-
-// === MEDIA URL extraction ===
-let media_url = if let Some(enclosure) = item.enclosure() {
-    // Prefer <enclosure url="...">
-    enclosure.url.clone()
-} else {
-    // Fallback: scrape from description + content:encoded
-    let description = item.description().unwrap_or_default();
-    let content_encoded = item.extensions().get("content")
-        .and_then(|c| c.get("encoded"))
-        .and_then(|e| e.first())
-        .and_then(|v| v.value.as_deref())
-        .unwrap_or("");
-
-    let combined = format!("{} {}", description, content_encoded);
-
-    if let Some(cap) = media_regex.captures(&combined) {
-        if let Some(url_match) = cap.get(1) {
-            url_match.as_str().to_string()
-        } else {
-            continue; // no match
+    // Process feeds using structured approach
+    let mut all_episodes: Vec<Episode> = Vec::new();
+    for feed in &fetched_feeds {
+        let parsed = parse_episodes(feed, &media_regex);
+        if let Some(example) = parsed.unparsed_dates.first() {
+            eprintln!(
+                "Warning: {}: {} publication date(s) could not be parsed, e.g. '{}'",
+                feed.subscription.name,
+                parsed.unparsed_dates.len(),
+                example
+            );
         }
+        all_episodes.extend(parsed.episodes);
+    }
+
+    // Media URLs are unwrapped before anything sees them, so every output and the database agree
+    let unwrapped = if matches.is_present("strip_trackers") {
+        let urls: HashSet<String> = all_episodes.iter().map(|e| e.media_url.clone()).collect();
+        let unwrapped = trackers::unwrap(&client, &trackers, urls.into_iter().collect(), matches.is_present("check_stripped")).await;
+        eprintln!("Unwrapped {} tracked media URL(s)", unwrapped.len());
+        unwrapped
     } else {
-        continue; // no media URL
+        HashMap::new()
+    };
+    for episode in &mut all_episodes {
+        if let Some(url) = unwrapped.get(&episode.media_url) {
+            episode.media_url = url.clone();
+        }
     }
-};
 
-// === PUB DATE fallback ===
-let date_str = item.pub_date()
-    .or_else(|| {
-        item.extensions().get("dc")
-            .and_then(|m| m.get("date"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.as_deref())
-    });
-
-let pub_date = date_str.and_then(|s| {
-    DateTime::parse_from_rfc2822(s)
-        .or_else(|_| DateTime::parse_from_rfc3339(s))
-        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%SZ"))
-        .ok()
-        .map(|dt| dt.with_timezone(&Utc))
-});
-
-// === TITLE fallback ===
-let title = item.title()
-    .map(|s| s.to_string())
-    .or_else(|| {
-        item.extensions().get("itunes")
-            .and_then(|m| m.get("title"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.clone())
-    })
-    .or_else(|| {
-        item.extensions().get("media")
-            .and_then(|m| m.get("title"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.clone())
-    })
-    .unwrap_or_else(|| "Unknown".to_string());
+    // The database keeps the full, unfiltered history with the dates the feeds gave
+    let run_id = match &mut store {
+        Some(store) => Some(store.record_run(now, &all_episodes)?),
+        None => None,
+    };
 
-// === Push the episode ===
-all_episodes.push(Episode {
-    feed_name: feed_name.clone(),
-    title,
-    pub_date,
-    media_url,
-});
+    // Fill in missing dates from whatever the chosen sources can tell
+    let first_seen = match (&store, run_id) {
+        (Some(store), Some(run_id)) if date_sources.contains(&DateSource::FirstSeen) => store.first_seen(run_id)?,
+        _ => HashMap::new(),
+    };
+    let inferred_dates = undated::infer_dates(&date_sources, &fetched_feeds, &all_episodes, &first_seen);
+    undated::apply(&mut all_episodes, &inferred_dates);
 
-                }
+    let (kept, rejected): (Vec<Episode>, Vec<Episode>) = all_episodes.into_iter().partition(|e| filters.matches(e));
+    let mut all_episodes = kept;
+    undated.retain(&mut all_episodes);
+
+    // Copies of an episode in several feeds: keep the one from the feed listed first
+    let dedup = matches.is_present("dedup");
+    let feed_rank: HashMap<String, usize> = fetched_feeds
+        .iter()
+        .enumerate()
+        .map(|(rank, feed)| (feed.subscription.url.clone(), rank))
+        .collect();
+    let duplicates = if dedup { dedup::dedup(&mut all_episodes, &feed_rank, &trackers) } else { Vec::new() };
+    for duplicate in &duplicates {
+        eprintln!("Duplicate: {}", duplicate.report());
+    }
+
+    // Process all episodes - from the episode store if one is configured, otherwise
+    // handle the two different approaches based on chronological flag
+    let mut new_episodes: Vec<Episode> = Vec::new();
+    let known: Vec<Episode>;
+    let (mut all_output, mut newest_episodes): (Vec<Episode>, Vec<Episode>) = if let (Some(store), Some(run_id)) = (&store, run_id) {
+        let prepare = |episodes: &mut Vec<Episode>, newest_first: bool| {
+            undated::apply(episodes, &inferred_dates);
+            episodes.retain(|episode| filters.matches(episode));
+            undated.retain(episodes);
+            if dedup {
+                dedup::dedup(episodes, &feed_rank, &trackers);
             }
+            undated.sort(episodes, newest_first);
+        };
+        if want_new {
+            new_episodes = store.new_since_previous_run(run_id)?;
+            prepare(&mut new_episodes, true);
         }
-        
+        let mut history = store.episodes(run_id)?;
+        prepare(&mut history, false);
+        known = history.clone();
+        // Played episodes leave the newest output before it is picked, so a feed whose
+        // latest episode is played still shows the one before
+        let newest = if matches.is_present("include_played") {
+            select::newest(&history, &newest_window, &selection, undated)
+        } else {
+            let played = store.played_episodes()?;
+            let unplayed = |e: &Episode| !played.contains(&(e.feed_url.clone(), e.guid.clone()));
+            new_episodes.retain(unplayed);
+            let candidates: Vec<Episode> = history.iter().filter(|e| unplayed(e)).cloned().collect();
+            select::newest(&candidates, &newest_window, &selection, undated)
+        };
+        history.retain(|episode| all_window.contains(episode));
+        (history, newest)
+    } else if chronological {
         // Sort episodes chronologically (oldest first)
-        all_episodes.sort_by(|a, b| {
-            match (&a.pub_date, &b.pub_date) {
-                (Some(a_date), Some(b_date)) => a_date.cmp(b_date),
-                (None, Some(_)) => std::cmp::Ordering::Less,
-                (Some(_), None) => std::cmp::Ordering::Greater,
-                (None, None) => std::cmp::Ordering::Equal,
-            }
-        });
-        
-        // Write all episodes to file in the requested format
-        write_episodes_to_file(
-            &all_episodes, 
-            &output_txt, 
-            all_files_format, 
-            "All Podcast Episodes"
-        );
-        
-        println!("Found {} episodes.", all_episodes.len());
-        
-        // Process feeds to extract newest episodes - only filter these by date in all cases
-        let mut newest_episodes_map: HashMap<String, Episode> = HashMap::new();
-        
-        // Filter a copy of all_episodes for newest collection
-        let filtered_episodes: Vec<Episode> = all_episodes
-            .iter()
-            .filter(|episode| {
-                if !check_current {
-                    return true;
-                }
-                if let Some(date) = episode.pub_date {
-                    let cutoff_date = Utc::now() - Duration::days(current_days);
-                    date >= cutoff_date
-                } else {
-                    false
-                }
-            })
-            .cloned()
-            .collect();
-        
-        for episode in filtered_episodes {
-            newest_episodes_map.entry(episode.feed_name.clone())
-                .or_insert(episode);
-        }
-        
-        let newest_episodes: Vec<Episode> = newest_episodes_map.into_values().collect();
-        
-        // Write newest episodes in each requested format
-        for format in &formats {
-            let newest_path = opml_path.parent().unwrap().join(format!("newest.{}", format));
-            write_episodes_to_file(
-                &newest_episodes, 
-                &newest_path, 
-                format, 
-                "Newest Podcast Episodes"
-            );
-        }
+        undated.sort(&mut all_episodes, false);
+        known = all_episodes.clone();
+
+        // Newest episodes are picked the same way on every code path
+        let newest = select::newest(&all_episodes, &newest_window, &selection, undated);
+        all_episodes.retain(|episode| all_window.contains(episode));
+        (all_episodes, newest)
     } else {
+        known = all_episodes.clone();
+
         // Original functionality - extract using regex for all files
         // This preserves backward compatibility with the original approach
         let mut media_urls: Vec<Episode> = Vec::new();
         
-        for (feed_name, content) in &raw_results {
-            // First extract through RSS for structured data
-            if let Ok(channel) = Channel::read_from(content.as_bytes()) {
-                for item in channel.items() {
-// --- Inside your for item in channel.items() loop ---
-
-// === MEDIA URL extraction ===
-let media_url = if let Some(enclosure) = item.enclosure() {
-    // Prefer <enclosure url="...">
-    enclosure.url.clone()
-} else {
-    // Fallback: scrape from description + content:encoded
-    let description = item.description().unwrap_or_default();
-    let content_encoded = item.extensions().get("content")
-        .and_then(|c| c.get("encoded"))
-        .and_then(|e| e.first())
-        .and_then(|v| v.value.as_deref())
-        .unwrap_or("");
-
-    let combined = format!("{} {}", description, content_encoded);
-
-    if let Some(cap) = media_regex.captures(&combined) {
-        if let Some(url_match) = cap.get(1) {
-            url_match.as_str().to_string()
-        } else {
-            continue; // no match
-        }
-    } else {
-        continue; // no media URL
-    }
-};
-
-// === PUB DATE fallback ===
-let date_str = item.pub_date()
-    .or_else(|| {
-        item.extensions().get("dc")
-            .and_then(|m| m.get("date"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.as_deref())
-    });
-
-let pub_date = date_str.and_then(|s| {
-    DateTime::parse_from_rfc2822(s)
-        .or_else(|_| DateTime::parse_from_rfc3339(s))
-        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%SZ"))
-        .ok()
-        .map(|dt| dt.with_timezone(&Utc))
-});
-
-
-// === TITLE fallback ===
-let title = item.title()
-    .map(|s| s.to_string())
-    .or_else(|| {
-        item.extensions().get("itunes")
-            .and_then(|m| m.get("title"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.clone())
-    })
-    .or_else(|| {
-        item.extensions().get("media")
-            .and_then(|m| m.get("title"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.clone())
-    })
-    .unwrap_or_else(|| "Unknown".to_string());
-
-// === Push the episode ===
-all_episodes.push(Episode {
-    feed_name: feed_name.clone(),
-    title,
-    pub_date,
-    media_url,
-});
-
-                }
-            }
-            
-            // Also search for URLs with regex
+        for feed in &fetched_feeds {
             let mut feed_urls: Vec<String> = Vec::new();
-            for cap in media_regex.captures_iter(content) {
+            for cap in media_regex.captures_iter(&feed.content) {
                 if let Some(url) = cap.get(1).map(|m| m.as_str().to_owned()) {
+                    let url = unwrapped.get(&url).cloned().unwrap_or(url);
                     if !feed_urls.contains(&url) {
                         feed_urls.push(url.clone());
                         // For URLs found with regex, we don't have structured data
                         media_urls.push(Episode {
-                            feed_name: feed_name.clone(),
+                            feed_name: feed.subscription.name.clone(),
+                            feed_url: feed.subscription.url.clone(),
+                            group: feed.subscription.group.clone(),
+                            guid: url.clone(),
                             title: "Unknown".to_string(),
                             pub_date: None,
                             media_url: url,
+                            enclosure_length: None,
+                            enclosure_type: None,
+                            description: None,
+                            episode_number: None,
+                            image_url: feed.subscription.image_url.clone(),
+                            duration: None,
+                            broken: None,
+                            cover: None,
+                            sources: feed.subscription.sources.clone(),
                         });
                     }
                 }
             }
         }
         
-        // Write all media URLs to output file in the requested format
-        write_episodes_to_file(
-            &media_urls, 
-            &output_txt, 
-            all_files_format, 
-            "All Podcast Episodes"
-        );
-        
-        println!("Found {} episodes with media URLs.", media_urls.len());
-        
-        // Process feeds to extract newest episodes - always filter these by date
-        let mut all_episodes: Vec<Episode> = Vec::new();
-        
-        for (feed_name, content) in &raw_results {
-            if let Ok(channel) = Channel::read_from(content.as_bytes()) {
-                for item in channel.items() {
-// --- Inside your for item in channel.items() loop ---
-
-// === MEDIA URL extraction ===
-let media_url = if let Some(enclosure) = item.enclosure() {
-    // Prefer <enclosure url="...">
-    enclosure.url.clone()
-} else {
-    // Fallback: scrape from description + content:encoded
-    let description = item.description().unwrap_or_default();
-    let content_encoded = item.extensions().get("content")
-        .and_then(|c| c.get("encoded"))
-        .and_then(|e| e.first())
-        .and_then(|v| v.value.as_deref())
-        .unwrap_or("");
-
-    let combined = format!("{} {}", description, content_encoded);
-
-    if let Some(cap) = media_regex.captures(&combined) {
-        if let Some(url_match) = cap.get(1) {
-            url_match.as_str().to_string()
-        } else {
-            continue; // no match
+        // Regex matches carry no title, description or length, so only the feed rules apply to
+        // them; the rest is judged on the parsed episodes filtered out above
+        let rejected_urls: HashSet<&str> = rejected.iter().map(|e| e.media_url.as_str()).collect();
+        media_urls.retain(|episode| filters.matches_feed(episode) && !rejected_urls.contains(episode.media_url.as_str()));
+        if dedup {
+            let duplicate_urls: HashSet<&str> = duplicates.iter().map(|d| d.media_url.as_str()).collect();
+            media_urls.retain(|episode| !duplicate_urls.contains(episode.media_url.as_str()));
+            dedup::dedup(&mut media_urls, &feed_rank, &trackers);
         }
-    } else {
-        continue; // no media URL
-    }
-};
-
-// === PUB DATE fallback ===
-let date_str = item.pub_date()
-    .or_else(|| {
-        item.extensions().get("dc")
-            .and_then(|m| m.get("date"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.as_deref())
-    });
-
-let pub_date = date_str.and_then(|s| {
-    DateTime::parse_from_rfc2822(s)
-        .or_else(|_| DateTime::parse_from_rfc3339(s))
-        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%SZ"))
-        .ok()
-        .map(|dt| dt.with_timezone(&Utc))
-});
-
-// === TITLE fallback ===
-let title = item.title()
-    .map(|s| s.to_string())
-    .or_else(|| {
-        item.extensions().get("itunes")
-            .and_then(|m| m.get("title"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.clone())
-    })
-    .or_else(|| {
-        item.extensions().get("media")
-            .and_then(|m| m.get("title"))
-            .and_then(|e| e.first())
-            .and_then(|e| e.value.clone())
-    })
-    .unwrap_or_else(|| "Unknown".to_string());
-
-// === Push the episode ===
-all_episodes.push(Episode {
-    feed_name: feed_name.clone(),
-    title,
-    pub_date,
-    media_url,
-});
+        if undated == Undated::Drop || all_window.since.is_some() || all_window.until.is_some() {
+            // Regex matches are undated; only URLs of episodes known to be dated (and in the window) survive
+            let dated_urls: HashSet<&str> = all_episodes
+                .iter()
+                .filter(|e| all_window.contains(e))
+                .map(|e| e.media_url.as_str())
+                .collect();
+            media_urls.retain(|episode| dated_urls.contains(episode.media_url.as_str()));
+        }
+        (media_urls, select::newest(&all_episodes, &newest_window, &selection, undated))
+    };
 
+    // Flag what the last link check found broken, so players can skip it
+    if let Some(store) = &store {
+        let broken = store.broken_links()?;
+        if !broken.is_empty() {
+            for episode in all_output.iter_mut().chain(newest_episodes.iter_mut()).chain(new_episodes.iter_mut()) {
+                episode.broken = broken.get(&episode.media_url).cloned();
             }
         }
-        
-        // For newest episodes, collect the newest per feed
-        let mut newest_episodes_map: HashMap<String, Episode> = HashMap::new();
-        
-        for episode in &all_episodes {
-            let entry = newest_episodes_map.entry(episode.feed_name.clone());
-            match entry {
-                std::collections::hash_map::Entry::Vacant(e) => {
-                    e.insert(episode.clone());
-                },
-                std::collections::hash_map::Entry::Occupied(mut e) => {
-                    if let (Some(existing_date), Some(new_date)) = (e.get().pub_date, episode.pub_date) {
-                        if new_date > existing_date {
-                            e.insert(episode.clone());
-                        }
-                    }
-                }
-            }
+    }
+
+    // Episodes from the database only know their feed, not the OPML lists it is in this time
+    if store.is_some() && opml_paths.len() > 1 {
+        let sources: HashMap<&str, &Vec<String>> = fetched_feeds
+            .iter()
+            .map(|feed| (feed.subscription.url.as_str(), &feed.subscription.sources))
+            .collect();
+        for episode in all_output.iter_mut().chain(newest_episodes.iter_mut()).chain(new_episodes.iter_mut()) {
+            episode.sources = sources.get(episode.feed_url.as_str()).map(|s| s.to_vec()).unwrap_or_default();
         }
-        
-        let newest_episodes: Vec<Episode> = newest_episodes_map.into_values().collect();
-        
-        // Write newest episodes in each requested format
-        for format in &formats {
-            let newest_path = opml_path.parent().unwrap().join(format!("newest.{}", format));
-            write_episodes_to_file(
-                &newest_episodes, 
-                &newest_path, 
-                format, 
-                "Newest Podcast Episodes"
-            );
+    }
+
+    // Point every episode at cached artwork, falling back to the artwork URL
+    if let Some(dir) = matches.value_of("covers") {
+        let size_arg = matches.value_of("cover_size").unwrap();
+        let size = size_arg
+            .parse::<u32>()
+            .ok()
+            .filter(|&size| size > 0)
+            .ok_or_else(|| Error::InvalidArgument(format!("--cover-size expects a number of pixels, got '{}'", size_arg)))?;
+        let lists = [&mut all_output, &mut newest_episodes, &mut new_episodes];
+        let urls: HashSet<String> = lists.iter().flat_map(|list| list.iter()).filter_map(|e| e.image_url.clone()).collect();
+        let cached = covers::cache_covers(&client, urls.into_iter().collect(), Path::new(dir), size).await?;
+        for episode in lists.into_iter().flat_map(|list| list.iter_mut()) {
+            episode.cover = episode.image_url.as_ref().map(|url| match cached.get(url) {
+                Some(path) => path.display().to_string(),
+                None => url.clone(),
+            });
         }
+        eprintln!("{} cover(s) cached in {}", cached.len(), dir);
     }
 
-    println!("Done. All episodes written to {}.", output_txt.display());
-    for format in &formats {
-        let newest_path = opml_path.parent().unwrap().join(format!("newest.{}", format));
-        println!("Newest episodes written to {}.", newest_path.display());
+    if let Some(budget) = selection.budget {
+        let total: u32 = newest_episodes.iter().filter_map(|e| e.duration).sum();
+        eprintln!(
+            "Budget: {} of {} filled with {} episode(s)",
+            duration::format(total),
+            duration::format(budget),
+            newest_episodes.len()
+        );
     }
+
+    if let (Some(store), false) = (&store, record) {
+        store.discard_run()?;
+    }
+
+    Ok(Collection {
+        all: all_output,
+        newest: newest_episodes,
+        new: new_episodes,
+        known,
+        feeds: fetched_feeds.into_iter().map(|feed| feed.subscription).collect(),
+        failed_feeds,
+        total_feeds,
+        store: store.filter(|_| record),
+    })
 }
 
-// Helper function to write episodes to a file in the specified format
-fn write_episodes_to_file(episodes: &[Episode], path: &PathBuf, format: &str, title: &str) {
-    match format {
-        "md" => {
-            // Write in Markdown format
-            let mut file = File::create(path).unwrap();
-            
-            writeln!(file, "# {}", title).unwrap();
-            writeln!(file).unwrap();
-            
-            for episode in episodes {
-                let date_str = episode.pub_date
-                    .map(|d| d.format("%Y-%m-%d").to_string())
-                    .unwrap_or_else(|| "Unknown date".to_string());
-                    
-                writeln!(
-                    file,
-                    "## {}\n\n**{}** [{}]\n\n[Listen]({})  \n",
-                    episode.feed_name,
-                    episode.title,
-                    date_str,
-                    episode.media_url
-                ).unwrap();
-            }
-        },
-        "html" => {
-            // Write in HTML format
-            let mut file = File::create(path).unwrap();
-            
-            writeln!(file, "<!DOCTYPE html>").unwrap();
-            writeln!(file, "<html>").unwrap();
-            writeln!(file, "<head>").unwrap();
-            writeln!(file, "    <meta charset=\"UTF-8\">").unwrap();
-            writeln!(file, "    <title>{}</title>", title).unwrap();
-            writeln!(file, "    <style>").unwrap();
-            writeln!(file, "        body {{ font-family: Arial, sans-serif; margin: 20px; }}").unwrap();
-            writeln!(file, "        h1 {{ color: #333; }}").unwrap();
-            writeln!(file, "        .episode {{ margin-bottom: 30px; border-bottom: 1px solid #eee; padding-bottom: 20px; }}").unwrap();
-            writeln!(file, "        .feed-name {{ font-size: 1.5em; color: #2c3e50; margin-bottom: 5px; }}").unwrap();
-            writeln!(file, "        .episode-title {{ font-weight: bold; font-size: 1.2em; }}").unwrap();
-            writeln!(file, "        .date {{ color: #7f8c8d; margin-bottom: 10px; }}").unwrap();
-            writeln!(file, "        .media-link {{ margin-top: 10px; }}").unwrap();
-            writeln!(file, "        .media-link a {{ color: #3498db; text-decoration: none; }}").unwrap();
-            writeln!(file, "        .media-link a:hover {{ text-decoration: underline; }}").unwrap();
-            writeln!(file, "    </style>").unwrap();
-            writeln!(file, "</head>").unwrap();
-            writeln!(file, "<body>").unwrap();
-            writeln!(file, "    <h1>{}</h1>", title).unwrap();
-            
-            for episode in episodes {
-                let date_str = episode.pub_date
-                    .map(|d| d.format("%Y-%m-%d").to_string())
-                    .unwrap_or_else(|| "Unknown date".to_string());
-                    
-                writeln!(file, "    <div class=\"episode\">").unwrap();
-                writeln!(file, "        <div class=\"feed-name\">{}</div>", html_escape(&episode.feed_name)).unwrap();
-                writeln!(file, "        <div class=\"episode-title\">{}</div>", html_escape(&episode.title)).unwrap();
-                writeln!(file, "        <div class=\"date\">{}</div>", date_str).unwrap();
-                writeln!(file, "        <div class=\"media-link\"><a href=\"{}\">Listen</a></div>", episode.media_url).unwrap();
-                writeln!(file, "    </div>").unwrap();
-            }
-            
-            writeln!(file, "</body>").unwrap();
-            writeln!(file, "</html>").unwrap();
-        },
-        _ => {
-            // Default plain text format
-            let mut file = File::create(path).unwrap();
-            for episode in episodes {
-                let date_str = episode.pub_date
-                    .map(|d| d.format("%Y-%m-%d").to_string())
-                    .unwrap_or_else(|| "Unknown date".to_string());
-                    
-                writeln!(
-                    file,
-                    "{}: {} [{}] - {}",
-                    episode.feed_name,
-                    episode.title,
-                    date_str,
-                    episode.media_url
-                ).unwrap();
-            }
+// The OPML files named on the command line, with directories replaced by the
+// .opml and .xml files in them, in name order
+fn opml_files(matches: &ArgMatches) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in matches.values_of("opml_file").unwrap().map(PathBuf::from) {
+        if !input.is_dir() {
+            files.push(input);
+            continue;
         }
+        let mut found: Vec<PathBuf> = std::fs::read_dir(&input)
+            .map_err(Error::io(&input))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.is_file()
+                    && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("opml") || ext.eq_ignore_ascii_case("xml"))
+            })
+            .collect();
+        if found.is_empty() {
+            eprintln!("Warning: no OPML files in {}", input.display());
+        }
+        found.sort();
+        files.extend(found);
     }
+    Ok(files)
 }
 
-fn parse_opml(path: &PathBuf) -> Vec<(String, String)> {
-    let file = File::open(path).expect("Cannot open OPML file");
+// The first list has always had its first feed outline passed over; lists added
+// alongside it are read whole, so none of their feeds go missing
+fn parse_opml(path: &Path, skip_first: bool) -> Result<Vec<Subscription>> {
+    let file = File::open(path).map_err(Error::io(path))?;
     let reader = BufReader::new(file);
 
     let text_re = Regex::new(r#"text="([^"]+)""#).unwrap();
     let url_re = Regex::new(r#"xmlUrl="([^"]+)""#).unwrap();
+    let image_re = Regex::new(r#"imageUrl="([^"]+)""#).unwrap();
 
     let mut feeds = vec![];
-    let mut found_first = false;
+    let mut found_first = !skip_first;
+    // Every open outline enclosing the current line: the folder name for folders
+    // (those without xmlUrl), None for feed outlines with children
+    let mut open: Vec<Option<String>> = Vec::new();
 
-    for line in reader.lines().filter_map(Result::ok) {
+    for line in reader.lines() {
+        let line = line.map_err(Error::io(path))?;
+        let opens = line.contains("<outline") && !line.contains("/>") && !line.contains("</outline>");
         if line.contains("xmlUrl=") {
+            if opens {
+                open.push(None);
+            }
             let name = text_re.captures(&line).and_then(|c| c.get(1)).map(|m| m.as_str());
             let url = url_re.captures(&line).and_then(|c| c.get(1)).map(|m| m.as_str());
 
@@ -622,32 +1600,90 @@ fn parse_opml(path: &PathBuf) -> Vec<(String, String)> {
                     found_first = true;
                     continue;
                 }
-                feeds.push((n.to_string(), u.to_string()));
+                feeds.push(Subscription {
+                    name: n.to_string(),
+                    url: u.to_string(),
+                    group: open.iter().rev().flatten().next().cloned(),
+                    image_url: image_re.captures(&line).and_then(|c| c.get(1)).map(|m| m.as_str().replace("&amp;", "&")),
+                    sources: Vec::new(),
+                });
             }
+        } else if opens {
+            let group = text_re.captures(&line).and_then(|c| c.get(1)).map(|m| m.as_str());
+            open.push(Some(group.unwrap_or_default().to_string()));
+        } else if line.contains("</outline>") && !line.contains("<outline") {
+            open.pop();
         }
     }
-    feeds
+    Ok(feeds)
 }
 
-async fn fetch_feed(client: &Client, name: &str, url: &str) -> Option<(String, String)> {
-    match client.get(url).send().await {
-        Ok(resp) => {
-            if resp.status() == StatusCode::OK {
-                if let Ok(content) = resp.text().await {
-                    return Some((name.to_string(), content));
-                }
-            }
-            None
-        }
-        Err(_) => None,
+async fn fetch_feed(client: &Client, subscription: Subscription) -> Result<FetchedFeed> {
+    let fetch_error = |reason: String| Error::Fetch { feed: subscription.name.clone(), reason };
+
+    let resp = client.get(&subscription.url).send().await.map_err(|e| fetch_error(e.to_string()))?;
+    if resp.status() != StatusCode::OK {
+        return Err(fetch_error(format!("HTTP {}", resp.status())));
     }
+    let last_modified = resp.headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+        .map(|d| d.with_timezone(&Utc));
+    let content = resp.text().await.map_err(|e| fetch_error(e.to_string()))?;
+    Ok(FetchedFeed { subscription, content, last_modified })
 }
 
-fn html_escape(s: &str) -> String {
-    s.replace("&", "&amp;")
-     .replace("<", "&lt;")
-     .replace(">", "&gt;")
-     .replace("\"", "&quot;")
-     .replace("'", "&#39;")
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server};
+    use std::convert::Infallible;
+
+    const FEED: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Feed One</title><link>http://x</link><description>d</description>
+<item><title>Interview with a guest</title><guid>e2</guid><pubDate>Sun, 18 Oct 2026 10:00:00 GMT</pubDate><enclosure url="http://example.com/e2.mp3" length="1000" type="audio/mpeg"/></item>
+<item><title>News roundup</title><guid>e1</guid><pubDate>Sat, 17 Oct 2026 10:00:00 GMT</pubDate><enclosure url="http://example.com/e1.mp3" length="1000" type="audio/mpeg"/></item>
+</channel></rss>"#;
+
+    /// Serves `FEED` on a local port and writes an OPML file listing it
+    fn subscriptions(name: &str) -> PathBuf {
+        let make = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_| async { Ok::<_, Infallible>(Response::new(Body::from(FEED))) }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make);
+        let port = server.local_addr().port();
+        tokio::spawn(server);
+
+        let dir = std::env::temp_dir().join(format!("powercrust-main-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("subs.opml");
+        std::fs::write(
+            &path,
+            format!(
+                "<opml version=\"1.0\"><body>\n<outline text=\"feeds\" xmlUrl=\"ignored-first\" />\n\
+                 <outline text=\"Feed One\" type=\"rss\" xmlUrl=\"http://127.0.0.1:{}/feed.xml\" />\n</body></opml>\n",
+                port
+            ),
+        )
+        .unwrap();
+        path
+    }
+
+    fn urls(episodes: &[Episode]) -> Vec<&str> {
+        episodes.iter().map(|e| e.media_url.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn include_filters_keep_matching_episodes_in_every_output() {
+        let opml = subscriptions("include");
+        let matches = cli().get_matches_from(["powercrust", opml.to_str().unwrap(), "--since", "all", "--include-title", "interview"]);
+        let now = "2026-10-19T00:00:00Z".parse().unwrap();
+        let collection = collect(&matches, now, false, false).await.unwrap();
+
+        assert_eq!(urls(&collection.all), ["http://example.com/e2.mp3"]);
+        assert_eq!(urls(&collection.newest), ["http://example.com/e2.mp3"]);
+        std::fs::remove_dir_all(opml.parent().unwrap()).unwrap();
+    }
 }
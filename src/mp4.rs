@@ -0,0 +1,518 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::atomic::AtomicFile;
+
+/// iTunes-style metadata for an MP4/M4A file (`moov/udta/meta/ilst`).
+pub struct Metadata<'a> {
+    pub title: &'a str,
+    pub album: &'a str,
+    pub date: Option<String>,
+    pub track: Option<u32>,
+    pub comment: Option<&'a str>,
+    /// Image bytes and MIME type; only JPEG and PNG can be stored
+    pub cover: Option<(&'a [u8], &'a str)>,
+}
+
+// Well-known data atom type indicators
+const TYPE_IMPLICIT: u32 = 0;
+const TYPE_UTF8: u32 = 1;
+const TYPE_JPEG: u32 = 13;
+const TYPE_PNG: u32 = 14;
+
+/// Write the metadata into the file, keeping any other ilst items it already has.
+/// The file is rewritten next to itself and renamed over the original.
+pub fn write_metadata(path: &Path, metadata: &Metadata) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let atoms = top_level_atoms(&mut file, len)?;
+    let moov = atoms
+        .iter()
+        .find(|atom| &atom.kind == b"moov")
+        .ok_or_else(|| invalid("no moov atom"))?;
+    // Fragments address their media relative to themselves (moof/traf), which rewriting
+    // moov does not update; such files are left untagged
+    if atoms.iter().any(|atom| &atom.kind == b"moof") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "fragmented MP4 files are not tagged"));
+    }
+
+    let mut old_moov = vec![0u8; moov.size as usize];
+    file.seek(SeekFrom::Start(moov.offset))?;
+    file.read_exact(&mut old_moov)?;
+
+    let items = metadata_items(metadata);
+    let replaced: Vec<[u8; 4]> = items.iter().map(|item| item_kind(item)).collect();
+    let mut new_moov = rebuild_moov(&old_moov[moov.header_len..], &items, &replaced)?;
+
+    // Media data behind the moov atom moves by the size difference
+    let delta = new_moov.len() as i64 - moov.size as i64;
+    if delta != 0 {
+        adjust_chunk_offsets(&mut new_moov, moov.offset + moov.size, delta)?;
+    }
+
+    let mut out = AtomicFile::create(path)?;
+    for atom in &atoms {
+        if atom.offset == moov.offset {
+            out.write_all(&new_moov)?;
+        } else {
+            file.seek(SeekFrom::Start(atom.offset))?;
+            io::copy(&mut (&mut file).take(atom.size), &mut out)?;
+        }
+    }
+    drop(file);
+    out.commit()
+}
+
+struct TopAtom {
+    kind: [u8; 4],
+    offset: u64,
+    header_len: usize,
+    size: u64,
+}
+
+fn top_level_atoms(file: &mut File, len: u64) -> io::Result<Vec<TopAtom>> {
+    let mut atoms = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (size, header_len) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (len - offset, 8),
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large)?;
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size as u64, 8),
+        };
+        if size < header_len as u64 || offset + size > len {
+            return Err(invalid("truncated atom"));
+        }
+        atoms.push(TopAtom { kind, offset, header_len, size });
+        offset += size;
+    }
+    Ok(atoms)
+}
+
+/// A child box inside an in-memory buffer: its type and where header and payload live.
+struct Child {
+    kind: [u8; 4],
+    start: usize,
+    payload: usize,
+    end: usize,
+}
+
+fn children(buf: &[u8]) -> io::Result<Vec<Child>> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= buf.len() {
+        let size32 = u32::from_be_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]]);
+        let kind = [buf[pos + 4], buf[pos + 5], buf[pos + 6], buf[pos + 7]];
+        let (size, header_len) = match size32 {
+            0 => (buf.len() - pos, 8),
+            1 if pos + 16 <= buf.len() => {
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&buf[pos + 8..pos + 16]);
+                (u64::from_be_bytes(large) as usize, 16)
+            }
+            size => (size as usize, 8),
+        };
+        if size < header_len || pos + size > buf.len() {
+            return Err(invalid("truncated box"));
+        }
+        out.push(Child { kind, start: pos, payload: pos + header_len, end: pos + size });
+        pos += size;
+    }
+    Ok(out)
+}
+
+fn boxed(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 16);
+    if payload.len() + 8 <= u32::MAX as usize {
+        out.extend_from_slice(&((payload.len() + 8) as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+    } else {
+        out.extend_from_slice(&1u32.to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(&((payload.len() + 16) as u64).to_be_bytes());
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+fn metadata_items(metadata: &Metadata) -> Vec<Vec<u8>> {
+    let mut items = vec![
+        text_item(b"\xa9nam", metadata.title),
+        text_item(b"\xa9alb", metadata.album),
+    ];
+    if let Some(date) = &metadata.date {
+        items.push(text_item(b"\xa9day", date));
+    }
+    if let Some(track) = metadata.track {
+        // trkn: reserved, track, total, reserved
+        let mut payload = vec![0u8, 0];
+        payload.extend_from_slice(&(track.min(u16::MAX as u32) as u16).to_be_bytes());
+        payload.extend_from_slice(&[0, 0, 0, 0]);
+        items.push(data_item(b"trkn", TYPE_IMPLICIT, &payload));
+    }
+    if let Some(comment) = metadata.comment {
+        items.push(text_item(b"\xa9cmt", comment));
+    }
+    if let Some((data, mime)) = metadata.cover {
+        let type_indicator = match mime {
+            "image/png" => Some(TYPE_PNG),
+            "image/jpeg" | "image/jpg" => Some(TYPE_JPEG),
+            _ => None,
+        };
+        if let Some(type_indicator) = type_indicator {
+            items.push(data_item(b"covr", type_indicator, data));
+        }
+    }
+    items
+}
+
+fn text_item(kind: &[u8; 4], text: &str) -> Vec<u8> {
+    data_item(kind, TYPE_UTF8, text.as_bytes())
+}
+
+fn data_item(kind: &[u8; 4], type_indicator: u32, value: &[u8]) -> Vec<u8> {
+    let mut data = type_indicator.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0, 0, 0, 0]); // locale
+    data.extend_from_slice(value);
+    boxed(kind, &boxed(b"data", &data))
+}
+
+fn item_kind(item: &[u8]) -> [u8; 4] {
+    [item[4], item[5], item[6], item[7]]
+}
+
+fn rebuild_moov(payload: &[u8], items: &[Vec<u8>], replaced: &[[u8; 4]]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(payload.len());
+    let mut has_udta = false;
+    for child in children(payload)? {
+        if &child.kind == b"udta" {
+            has_udta = true;
+            let udta = rebuild_udta(&payload[child.payload..child.end], items, replaced)?;
+            out.extend_from_slice(&boxed(b"udta", &udta));
+        } else {
+            out.extend_from_slice(&payload[child.start..child.end]);
+        }
+    }
+    if !has_udta {
+        out.extend_from_slice(&boxed(b"udta", &rebuild_meta(None, items, replaced)?));
+    }
+    Ok(boxed(b"moov", &out))
+}
+
+fn rebuild_udta(payload: &[u8], items: &[Vec<u8>], replaced: &[[u8; 4]]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(payload.len());
+    let mut meta = None;
+    for child in children(payload)? {
+        if &child.kind == b"meta" {
+            meta = Some(&payload[child.payload..child.end]);
+        } else {
+            out.extend_from_slice(&payload[child.start..child.end]);
+        }
+    }
+    out.extend_from_slice(&rebuild_meta(meta, items, replaced)?);
+    Ok(out)
+}
+
+// The meta box with `items` added to its ilst. Existing items we are not about to
+// overwrite stay, as do the other children (hdlr, keys, free, ...) in their order
+fn rebuild_meta(meta: Option<&[u8]>, items: &[Vec<u8>], replaced: &[[u8; 4]]) -> io::Result<Vec<u8>> {
+    let mut kept_children = Vec::new();
+    let mut ilst = Vec::new();
+    let mut ilst_at = None;
+    let mut has_hdlr = false;
+    if let Some(meta) = meta {
+        // meta is a full box in ISO files, but QuickTime files omit version and flags
+        let body = if meta.len() >= 8 && &meta[4..8] == b"hdlr" { meta } else { &meta[meta.len().min(4)..] };
+        for child in children(body)? {
+            if &child.kind == b"ilst" {
+                let items = &body[child.payload..child.end];
+                for item in children(items)? {
+                    if !replaced.contains(&item.kind) {
+                        ilst.extend_from_slice(&items[item.start..item.end]);
+                    }
+                }
+                ilst_at.get_or_insert(kept_children.len());
+            } else {
+                has_hdlr |= &child.kind == b"hdlr";
+                kept_children.push(&body[child.start..child.end]);
+            }
+        }
+    }
+    ilst.extend(items.iter().flatten());
+
+    let mut hdlr = vec![0u8; 8]; // version, flags, pre_defined
+    hdlr.extend_from_slice(b"mdirappl");
+    hdlr.extend_from_slice(&[0u8; 9]); // reserved, empty name
+    let hdlr = boxed(b"hdlr", &hdlr);
+    if !has_hdlr {
+        kept_children.insert(0, &hdlr);
+        ilst_at = ilst_at.map(|at| at + 1);
+    }
+    let ilst = boxed(b"ilst", &ilst);
+    kept_children.insert(ilst_at.unwrap_or(kept_children.len()), &ilst);
+
+    let mut payload = vec![0u8; 4]; // version, flags
+    payload.extend(kept_children.into_iter().flatten());
+    Ok(boxed(b"meta", &payload))
+}
+
+// Shift stco/co64 entries that point past `threshold` by `delta` bytes
+fn adjust_chunk_offsets(buf: &mut [u8], threshold: u64, delta: i64) -> io::Result<()> {
+    let header_len = if buf.len() >= 4 && buf[..4] == [0, 0, 0, 1] { 16 } else { 8 };
+    let payload_start = header_len.min(buf.len());
+    let boxes = children(&buf[payload_start..])?;
+    for child in boxes {
+        let start = payload_start + child.start;
+        let end = payload_start + child.end;
+        match &child.kind {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => {
+                adjust_chunk_offsets(&mut buf[start..end], threshold, delta)?;
+            }
+            b"stco" | b"co64" => {
+                let wide = &child.kind == b"co64";
+                let body = &mut buf[payload_start + child.payload..end];
+                if body.len() < 8 {
+                    return Err(invalid("truncated chunk offset table"));
+                }
+                let count = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+                let width = if wide { 8 } else { 4 };
+                if body.len() < 8 + count * width {
+                    return Err(invalid("truncated chunk offset table"));
+                }
+                for i in 0..count {
+                    let at = 8 + i * width;
+                    let entry = &mut body[at..at + width];
+                    let offset = if wide {
+                        u64::from_be_bytes(entry.try_into().unwrap())
+                    } else {
+                        u32::from_be_bytes(entry.try_into().unwrap()) as u64
+                    };
+                    if offset < threshold {
+                        continue;
+                    }
+                    let moved = (offset as i64 + delta) as u64;
+                    if wide {
+                        entry.copy_from_slice(&moved.to_be_bytes());
+                    } else {
+                        let moved = u32::try_from(moved).map_err(|_| invalid("chunk offset overflows stco"))?;
+                        entry.copy_from_slice(&moved.to_be_bytes());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("not a valid MP4 file: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boxed, children, write_metadata, Metadata};
+    use std::{fs, path::PathBuf};
+
+    const MEDIA: &[u8] = b"first chunk|second chunk";
+
+    // A minimal file: ftyp, then moov and mdat in either order, with one track whose
+    // chunk offset table (stco or co64) points at the two chunks in mdat
+    fn sample(moov_first: bool, wide: bool, udta: Option<Vec<u8>>) -> Vec<u8> {
+        let ftyp = boxed(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        let moov = |offsets: &[u64]| {
+            let mut table = vec![0u8; 4];
+            table.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+            for &offset in offsets {
+                if wide {
+                    table.extend_from_slice(&offset.to_be_bytes());
+                } else {
+                    table.extend_from_slice(&(offset as u32).to_be_bytes());
+                }
+            }
+            let stbl = boxed(b"stbl", &boxed(if wide { b"co64" } else { b"stco" }, &table));
+            let trak = boxed(b"trak", &boxed(b"mdia", &boxed(b"minf", &stbl)));
+            let mut payload = boxed(b"mvhd", &[0u8; 100]);
+            payload.extend_from_slice(&trak);
+            if let Some(udta) = &udta {
+                payload.extend_from_slice(&boxed(b"udta", udta));
+            }
+            boxed(b"moov", &payload)
+        };
+        let mdat = boxed(b"mdat", MEDIA);
+        // The moov size does not depend on the offsets, so measure it with placeholders
+        let moov_len = moov(&[0, 0]).len() as u64;
+        let media_at = ftyp.len() as u64 + if moov_first { moov_len } else { 0 } + 8;
+        let moov = moov(&[media_at, media_at + 12]);
+
+        let mut file = ftyp;
+        if moov_first {
+            file.extend_from_slice(&moov);
+            file.extend_from_slice(&mdat);
+        } else {
+            file.extend_from_slice(&mdat);
+            file.extend_from_slice(&moov);
+        }
+        file
+    }
+
+    fn metadata() -> Metadata<'static> {
+        Metadata {
+            title: "Episode One",
+            album: "Some Feed",
+            date: Some("2026-09-01".to_string()),
+            track: Some(7),
+            comment: None,
+            cover: None,
+        }
+    }
+
+    fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("powercrust-mp4-{}-{}.m4a", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    // Payload of the first box along `path`, descending from the top level
+    fn find<'a>(mut buf: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+        for kind in path {
+            let child = children(buf).unwrap().into_iter().find(|c| &c.kind == *kind)?;
+            buf = &buf[child.payload..child.end];
+            // meta is a full box: skip version and flags
+            if *kind == b"meta" {
+                buf = &buf[4..];
+            }
+        }
+        Some(buf)
+    }
+
+    fn item_kinds(file: &[u8]) -> Vec<[u8; 4]> {
+        let ilst = find(file, &[b"moov", b"udta", b"meta", b"ilst"]).expect("no ilst");
+        children(ilst).unwrap().iter().map(|item| item.kind).collect()
+    }
+
+    fn text_value(file: &[u8], kind: &[u8; 4]) -> String {
+        let ilst = find(file, &[b"moov", b"udta", b"meta", b"ilst"]).unwrap();
+        let data = find(ilst, &[kind, b"data"]).unwrap();
+        String::from_utf8(data[8..].to_vec()).unwrap()
+    }
+
+    // Every chunk offset still points at the media it pointed at before tagging
+    fn assert_chunks_intact(file: &[u8], wide: bool) {
+        let stbl = find(file, &[b"moov", b"trak", b"mdia", b"minf", b"stbl"]).unwrap();
+        let table = find(stbl, &[if wide { b"co64" } else { b"stco" }]).unwrap();
+        let width = if wide { 8 } else { 4 };
+        let offsets: Vec<usize> = table[8..]
+            .chunks(width)
+            .map(|entry| match wide {
+                true => u64::from_be_bytes(entry.try_into().unwrap()) as usize,
+                false => u32::from_be_bytes(entry.try_into().unwrap()) as usize,
+            })
+            .collect();
+        assert_eq!(&file[offsets[0]..offsets[0] + 11], b"first chunk");
+        assert_eq!(&file[offsets[1]..offsets[1] + 12], b"second chunk");
+    }
+
+    fn tag(name: &str, original: &[u8]) -> Vec<u8> {
+        let path = temp_file(name, original);
+        write_metadata(&path, &metadata()).unwrap();
+        let tagged = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        tagged
+    }
+
+    #[test]
+    fn moov_before_mdat_shifts_chunk_offsets() {
+        let original = sample(true, false, None);
+        let tagged = tag("faststart", &original);
+        assert!(tagged.len() > original.len());
+        assert_chunks_intact(&original, false);
+        assert_chunks_intact(&tagged, false);
+        assert_eq!(text_value(&tagged, b"\xa9nam"), "Episode One");
+        assert_eq!(text_value(&tagged, b"\xa9alb"), "Some Feed");
+    }
+
+    #[test]
+    fn moov_after_mdat_keeps_chunk_offsets() {
+        let original = sample(false, false, None);
+        let tagged = tag("moov-last", &original);
+        // Everything before moov, the media included, stays where it was
+        let moov_at = children(&original).unwrap().into_iter().find(|c| &c.kind == b"moov").unwrap().start;
+        assert_eq!(&tagged[..moov_at], &original[..moov_at]);
+        assert_chunks_intact(&tagged, false);
+        assert_eq!(text_value(&tagged, b"\xa9day"), "2026-09-01");
+    }
+
+    #[test]
+    fn co64_offsets_are_shifted() {
+        let tagged = tag("co64", &sample(true, true, None));
+        assert_chunks_intact(&tagged, true);
+    }
+
+    #[test]
+    fn existing_items_are_kept() {
+        let data = |value: &[u8]| {
+            let mut payload = vec![0, 0, 0, 1, 0, 0, 0, 0];
+            payload.extend_from_slice(value);
+            boxed(b"data", &payload)
+        };
+        let mut ilst = boxed(b"\xa9nam", &data(b"Old title"));
+        ilst.extend_from_slice(&boxed(b"\xa9ART", &data(b"Some Host")));
+        let mut meta = vec![0u8; 4];
+        meta.extend_from_slice(&boxed(b"hdlr", &[0u8; 25]));
+        meta.extend_from_slice(&boxed(b"ilst", &ilst));
+        meta.extend_from_slice(&boxed(b"free", &[0u8; 16]));
+        let mut udta = boxed(b"meta", &meta);
+        udta.extend_from_slice(&boxed(b"name", b"kept"));
+
+        let tagged = tag("existing", &sample(true, false, Some(udta)));
+        assert_chunks_intact(&tagged, false);
+        let kinds = item_kinds(&tagged);
+        assert!(kinds.contains(b"\xa9ART"));
+        assert_eq!(kinds.iter().filter(|kind| *kind == b"\xa9nam").count(), 1);
+        assert_eq!(text_value(&tagged, b"\xa9nam"), "Episode One");
+        assert_eq!(text_value(&tagged, b"\xa9ART"), "Some Host");
+        assert_eq!(find(&tagged, &[b"moov", b"udta", b"name"]), Some(&b"kept"[..]));
+        let meta = find(&tagged, &[b"moov", b"udta", b"meta"]).unwrap();
+        let meta_kinds: Vec<[u8; 4]> = children(meta).unwrap().iter().map(|child| child.kind).collect();
+        assert_eq!(meta_kinds, [*b"hdlr", *b"ilst", *b"free"]);
+        assert_eq!(find(meta, &[b"free"]), Some(&[0u8; 16][..]));
+    }
+
+    #[test]
+    fn fragmented_files_are_left_alone() {
+        let mut fragmented = sample(true, false, None);
+        fragmented.extend_from_slice(&boxed(b"moof", &boxed(b"traf", &[0u8; 16])));
+        fragmented.extend_from_slice(&boxed(b"mdat", MEDIA));
+        let path = temp_file("fragmented", &fragmented);
+        assert!(write_metadata(&path, &metadata()).is_err());
+        assert_eq!(fs::read(&path).unwrap(), fragmented);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn broken_files_are_rejected_untouched() {
+        let complete = sample(true, false, None);
+        let broken: [(&str, &[u8]); 3] = [
+            ("truncated", &complete[..complete.len() - 5]),
+            ("garbage", b"this is not an mp4 file at all, just some text"),
+            ("no-moov", &boxed(b"mdat", MEDIA)),
+        ];
+        for (name, bytes) in broken {
+            let path = temp_file(name, bytes);
+            assert!(write_metadata(&path, &metadata()).is_err(), "{} was accepted", name);
+            assert_eq!(fs::read(&path).unwrap(), bytes, "{} was changed", name);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
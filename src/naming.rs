@@ -0,0 +1,287 @@
+use regex::{Captures, Regex};
+use reqwest::Url;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::dates;
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+
+pub const DEFAULT_TEMPLATE: &str = "{group}/{feed}/{date:%Y-%m-%d} - {title}.{ext}";
+
+// Longest path component we produce, in bytes. Filesystems allow 255; this leaves
+// room for the collision suffix and the `.part` extension of partial downloads.
+const MAX_COMPONENT_BYTES: usize = 180;
+
+const PLACEHOLDER: &str = r"\{(\w+)(?::([^}]*))?\}";
+
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp3", "m4a", "m4b", "mp4", "m4v", "aac", "ogg", "oga", "opus", "flac", "wav", "webm", "mov",
+];
+
+/// Check the `{date:<strftime>}` placeholders of a template, so a bad one is reported
+/// before anything is fetched.
+pub fn check_template(template: &str) -> Result<()> {
+    let placeholder = Regex::new(PLACEHOLDER).unwrap();
+    let sample = chrono::Utc::now();
+    for caps in placeholder.captures_iter(template) {
+        if let ("date", Some(date_format)) = (&caps[1], caps.get(2)) {
+            if dates::format_date(sample, date_format.as_str()).is_none() {
+                return Err(Error::InvalidArgument(format!("invalid date format {} in --name-template", &caps[0])));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Work out a download path under `dir` for every episode from a template such as
+/// `{group}/{feed}/{date:%Y-%m-%d} - {title}.{ext}`.
+///
+/// Placeholders: `{group}`, `{feed}`, `{title}`, `{date}` / `{date:<strftime>}`, `{ext}`
+/// and `{id}` (a short hash of the guid). Every path component is sanitized and
+/// length-limited on its own, empty components (e.g. no group) are dropped, and
+/// episodes that would share a path get their `{id}` appended (and numbered, if even
+/// that is taken) to keep them apart.
+/// `owner` tells the feed URL and guid of the episode an existing file was downloaded
+/// for, so a file from an earlier run is not mistaken for another episode's.
+pub fn plan_paths(
+    episodes: &[Episode],
+    template: &str,
+    dir: &Path,
+    owner: impl Fn(&Path) -> Option<(String, String)>,
+) -> Vec<PathBuf> {
+    let placeholder = Regex::new(PLACEHOLDER).unwrap();
+
+    // Compared case-insensitively, as on Windows and macOS
+    let mut taken: HashSet<String> = HashSet::new();
+    episodes
+        .iter()
+        .map(|episode| {
+            let free = |path: &Path, taken: &HashSet<String>| {
+                !taken.contains(&path.to_string_lossy().to_lowercase())
+                    && owner(&dir.join(path)).is_none_or(|(feed_url, guid)| feed_url == episode.feed_url && guid == episode.guid)
+            };
+            // The same guid can come with the same title in two feeds, so the suffixed
+            // path is checked too, and numbered until it is free
+            let id = short_id(&episode.guid);
+            let mut path = render(&placeholder, template, episode, None);
+            let mut n = 1;
+            while !free(&path, &taken) {
+                let suffix = if n == 1 { id.clone() } else { format!("{}-{}", id, n) };
+                path = render(&placeholder, template, episode, Some(&suffix));
+                n += 1;
+            }
+            taken.insert(path.to_string_lossy().to_lowercase());
+            dir.join(path)
+        })
+        .collect()
+}
+
+fn render(placeholder: &Regex, template: &str, episode: &Episode, suffix: Option<&str>) -> PathBuf {
+    let segments: Vec<&str> = template.split('/').collect();
+    let last = segments.len() - 1;
+
+    let mut path = PathBuf::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let rendered = placeholder.replace_all(segment, |caps: &Captures| expand(caps, episode));
+        let mut component = sanitize(&rendered);
+        if i == last {
+            component = limit_file_name(&component, suffix);
+        } else {
+            component = truncate_bytes(&component, MAX_COMPONENT_BYTES).to_string();
+        }
+        if !component.is_empty() {
+            path.push(component);
+        }
+    }
+    path
+}
+
+fn expand(caps: &Captures, episode: &Episode) -> String {
+    match &caps[1] {
+        "group" => episode.group.clone().unwrap_or_default(),
+        "feed" => episode.feed_name.clone(),
+        "title" => episode.title.clone(),
+        "date" => {
+            let date_format = caps.get(2).map(|m| m.as_str()).unwrap_or("%Y-%m-%d");
+            episode.pub_date
+                .map(|d| dates::format_date(d, date_format).unwrap_or_default())
+                .unwrap_or_else(|| "undated".to_string())
+        }
+        "ext" => extension(episode).to_string(),
+        "id" => short_id(&episode.guid),
+        _ => caps[0].to_string(),
+    }
+}
+
+/// File extension for an episode. The enclosure MIME type wins, since tracking
+/// redirects (`podtrac.com/pts/redirect.mp3/...`) make the URL unreliable.
+fn extension(episode: &Episode) -> &'static str {
+    episode.enclosure_type
+        .as_deref()
+        .and_then(extension_for_mime)
+        .or_else(|| extension_from_url(&episode.media_url))
+        .unwrap_or("mp3")
+}
+
+fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    let essence = mime.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    Some(match essence.as_str() {
+        "audio/mpeg" | "audio/mp3" | "audio/mpeg3" | "audio/x-mpeg" | "audio/x-mp3" => "mp3",
+        "audio/mp4" | "audio/x-m4a" | "audio/m4a" | "audio/aac" | "audio/x-aac" => "m4a",
+        "audio/x-m4b" => "m4b",
+        "video/mp4" | "video/x-mp4" => "mp4",
+        "video/x-m4v" => "m4v",
+        "video/quicktime" => "mov",
+        "audio/ogg" | "application/ogg" => "ogg",
+        "audio/opus" => "opus",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/webm" | "video/webm" => "webm",
+        _ => return None,
+    })
+}
+
+fn extension_from_url(url: &str) -> Option<&'static str> {
+    let url = Url::parse(url).ok()?;
+    let file_name = url.path_segments()?.next_back()?.to_ascii_lowercase();
+    let (_, ext) = file_name.rsplit_once('.')?;
+    MEDIA_EXTENSIONS.iter().copied().find(|&known| known == ext)
+}
+
+/// Make a single path component safe on Linux, macOS and Windows alike.
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    // Windows drops trailing dots and spaces; a leading dot would hide the file
+    let cleaned = cleaned.trim_end_matches(['.', ' ']).trim_start_matches(['.', ' ']);
+    if is_reserved_windows_name(cleaned) {
+        return format!("_{}", cleaned);
+    }
+    cleaned.to_string()
+}
+
+fn is_reserved_windows_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit())
+}
+
+// Shorten the stem, never the extension, and add the collision suffix if any
+fn limit_file_name(file_name: &str, suffix: Option<&str>) -> String {
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() <= 5 => (stem, Some(ext)),
+        _ => (file_name, None),
+    };
+    let suffix = suffix.map(|id| format!(" [{}]", id)).unwrap_or_default();
+    let reserved = suffix.len() + ext.map(|e| e.len() + 1).unwrap_or(0);
+    let stem = truncate_bytes(stem, MAX_COMPONENT_BYTES.saturating_sub(reserved)).trim_end();
+    match ext {
+        Some(ext) => format!("{}{}.{}", stem, suffix, ext),
+        None => format!("{}{}", stem, suffix),
+    }
+}
+
+fn truncate_bytes(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// FNV-1a, so the id of an episode (or a cover) is the same on every run and platform.
+pub fn short_id(guid: &str) -> String {
+    let hash = guid.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    format!("{:08x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn episode(title: &str, guid: &str) -> Episode {
+        Episode {
+            feed_name: "Feed".to_string(),
+            feed_url: "https://example.com/feed.xml".to_string(),
+            group: None,
+            guid: guid.to_string(),
+            title: title.to_string(),
+            pub_date: Some(Utc.with_ymd_and_hms(2026, 9, 1, 10, 0, 0).unwrap()),
+            media_url: format!("https://example.com/{}.mp3", guid),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: None,
+            duration: None,
+            broken: None,
+            cover: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn suffixes_episodes_that_would_share_a_path() {
+        let episodes = [episode("Rerun", "a"), episode("rerun", "b"), episode("Other", "c")];
+        let paths = plan_paths(&episodes, "{title}.{ext}", Path::new("lib"), |_| None);
+        assert_eq!(
+            names(&paths),
+            ["Rerun.mp3".to_string(), format!("rerun [{}].mp3", short_id("b")), "Other.mp3".to_string()]
+        );
+    }
+
+    #[test]
+    fn numbers_suffixed_paths_that_are_taken_too() {
+        let other_feed = Episode { feed_url: "https://example.com/other.xml".to_string(), ..episode("Rerun", "a") };
+        let episodes = [episode("Rerun", "a"), other_feed.clone(), other_feed];
+        let paths = plan_paths(&episodes, "{title}.{ext}", Path::new("lib"), |_| None);
+        let id = short_id("a");
+        assert_eq!(
+            names(&paths),
+            ["Rerun.mp3".to_string(), format!("Rerun [{}].mp3", id), format!("Rerun [{}-2].mp3", id)]
+        );
+    }
+
+    #[test]
+    fn suffixes_only_files_another_episode_owns() {
+        let episodes = [episode("Mine", "a"), episode("Theirs", "b")];
+        let owner = |path: &Path| {
+            let owner = match path.file_name()?.to_str()? {
+                "Mine.mp3" => "a",
+                "Theirs.mp3" => "someone else",
+                _ => return None,
+            };
+            Some(("https://example.com/feed.xml".to_string(), owner.to_string()))
+        };
+        let paths = plan_paths(&episodes, "{title}.{ext}", Path::new("lib"), owner);
+        assert_eq!(names(&paths), ["Mine.mp3".to_string(), format!("Theirs [{}].mp3", short_id("b"))]);
+    }
+
+    #[test]
+    fn checks_date_formats() {
+        assert!(check_template(DEFAULT_TEMPLATE).is_ok());
+        assert!(check_template("{feed}/{date}.{ext}").is_ok());
+        assert!(check_template("{feed}/{date:%Q}.{ext}").is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::atomic::AtomicFile;
+use crate::covers;
+use crate::dates;
+use crate::duration;
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+use crate::listing;
+
+/// Destination of a rendered output: a file on disk or stdout (`-`).
+pub enum OutputTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+impl OutputTarget {
+    /// Resolve a filename pattern such as `newest.{fmt}` or
+    /// `{name}-{date:%Y%m%d}.{fmt}` against the output directory.
+    /// A pattern of `-` selects stdout.
+    pub fn resolve(pattern: &str, out_dir: &Path, name: &str, format: &str, now: DateTime<Utc>) -> Result<OutputTarget> {
+        if pattern == "-" {
+            return Ok(OutputTarget::Stdout);
+        }
+        Ok(OutputTarget::File(out_dir.join(expand_pattern(pattern, name, format, now)?)))
+    }
+
+    pub fn display(&self) -> String {
+        match self {
+            OutputTarget::Stdout => "stdout".to_string(),
+            OutputTarget::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+// Supported placeholders: {name} (OPML file stem), {fmt}, {date} and {date:<strftime>}
+fn expand_pattern(pattern: &str, name: &str, format: &str, now: DateTime<Utc>) -> Result<String> {
+    let placeholder = Regex::new(r"\{(\w+)(?::([^}]*))?\}").unwrap();
+    let mut invalid = None;
+    let expanded = placeholder
+        .replace_all(pattern, |caps: &Captures| match &caps[1] {
+            "name" => name.to_string(),
+            "fmt" => extension(format).to_string(),
+            "date" => {
+                let date_format = caps.get(2).map(|m| m.as_str()).unwrap_or("%Y-%m-%d");
+                dates::format_date(now, date_format).unwrap_or_else(|| {
+                    invalid = Some(caps[0].to_string());
+                    String::new()
+                })
+            }
+            _ => caps[0].to_string(),
+        })
+        .into_owned();
+    match invalid {
+        Some(placeholder) => Err(Error::InvalidArgument(format!("invalid date format {} in '{}'", placeholder, pattern))),
+        None => Ok(expanded),
+    }
+}
+
+// The player is an HTML page too, but must not take the html output's filename
+fn extension(format: &str) -> &str {
+    match format {
+        "player" => "player.html",
+        _ => format,
+    }
+}
+
+/// Directory outputs go to when `--out-dir` is not given: next to the OPML file,
+/// or in the directory of OPML files.
+pub fn default_out_dir(opml_path: &Path) -> PathBuf {
+    if opml_path.is_dir() {
+        return opml_path.to_path_buf();
+    }
+    match opml_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Outputs rendered during a run. Files are staged next to their destination
+/// and only replace the previous outputs once all of them rendered successfully,
+/// so a failed run leaves the last good outputs in place.
+#[derive(Default)]
+pub struct StagedOutputs {
+    files: Vec<AtomicFile>,
+}
+
+impl StagedOutputs {
+    // Write episodes to the target in the specified format
+    pub fn write_episodes(&mut self, target: &OutputTarget, episodes: &[Episode], format: &str, title: &str) -> Result<()> {
+        // Cached covers have absolute paths; a file output points at them relative to
+        // itself, so the output and the cache can move together
+        let base = match target {
+            OutputTarget::File(path) => path.parent().and_then(|dir| dir.canonicalize().ok()),
+            OutputTarget::Stdout => None,
+        };
+        let relocated: Vec<Episode>;
+        let episodes = match base {
+            Some(base) if episodes.iter().any(has_cached_cover) => {
+                relocated = episodes
+                    .iter()
+                    .map(|episode| {
+                        let mut episode = episode.clone();
+                        if has_cached_cover(&episode) {
+                            let cover = covers::relative_to(Path::new(episode.cover.as_ref().unwrap()), &base);
+                            episode.cover = Some(cover.display().to_string());
+                        }
+                        episode
+                    })
+                    .collect();
+                &relocated
+            }
+            _ => episodes,
+        };
+        self.write(target, |out| render_episodes(out, episodes, format, title))
+    }
+
+    /// Stage whatever `render` writes for the target; stdout is written straight away.
+    pub fn write(&mut self, target: &OutputTarget, render: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> Result<()> {
+        match target {
+            OutputTarget::Stdout => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                render(&mut out)
+                    .and_then(|_| out.flush())
+                    .map_err(|source| Error::Io { target: target.display(), source })
+            }
+            OutputTarget::File(path) => {
+                let mut file = AtomicFile::create(path).map_err(Error::io(path))?;
+                render(&mut file).map_err(Error::io(path))?;
+                self.files.push(file);
+                Ok(())
+            }
+        }
+    }
+
+    /// Move every staged file into place.
+    pub fn commit(self) -> Result<()> {
+        for file in self.files {
+            let path = file.path().to_path_buf();
+            file.commit().map_err(Error::io(&path))?;
+        }
+        Ok(())
+    }
+}
+
+const PLAYER_TEMPLATE: &str = include_str!("../player/template.htm");
+
+fn render_episodes<W: Write + ?Sized>(out: &mut W, episodes: &[Episode], format: &str, title: &str) -> io::Result<()> {
+    match format {
+        "md" => {
+            // Write in Markdown format
+            writeln!(out, "# {}", title)?;
+            writeln!(out)?;
+
+            for episode in episodes {
+                if let Some(cover) = cover(episode) {
+                    writeln!(out, "![cover]({})\n", cover)?;
+                }
+                writeln!(
+                    out,
+                    "## {}\n\n**{}** [{}]{}\n\n{}  \n",
+                    episode.feed_name,
+                    episode.title,
+                    date_and_length(episode),
+                    sources_label(episode).map(|s| format!(" *{}*", s)).unwrap_or_default(),
+                    match &episode.broken {
+                        Some(problem) => format!("~~[Listen]({})~~ broken: {}", episode.media_url, problem),
+                        None => format!("[Listen]({})", episode.media_url),
+                    }
+                )?;
+            }
+        },
+        "html" => {
+            // Write in HTML format
+            writeln!(out, "<!DOCTYPE html>")?;
+            writeln!(out, "<html>")?;
+            writeln!(out, "<head>")?;
+            writeln!(out, "    <meta charset=\"UTF-8\">")?;
+            writeln!(out, "    <title>{}</title>", title)?;
+            writeln!(out, "    <style>")?;
+            writeln!(out, "        body {{ font-family: Arial, sans-serif; margin: 20px; }}")?;
+            writeln!(out, "        h1 {{ color: #333; }}")?;
+            writeln!(out, "        .episode {{ margin-bottom: 30px; border-bottom: 1px solid #eee; padding-bottom: 20px; overflow: auto; }}")?;
+            writeln!(out, "        .feed-name {{ font-size: 1.5em; color: #2c3e50; margin-bottom: 5px; }}")?;
+            writeln!(out, "        .episode-title {{ font-weight: bold; font-size: 1.2em; }}")?;
+            writeln!(out, "        .date {{ color: #7f8c8d; margin-bottom: 10px; }}")?;
+            writeln!(out, "        .sources {{ color: #7f8c8d; font-size: 0.9em; margin-bottom: 10px; }}")?;
+            writeln!(out, "        .cover {{ float: left; width: 96px; height: 96px; object-fit: cover; margin-right: 15px; }}")?;
+            writeln!(out, "        .media-link {{ margin-top: 10px; }}")?;
+            writeln!(out, "        .media-link a {{ color: #3498db; text-decoration: none; }}")?;
+            writeln!(out, "        .media-link a:hover {{ text-decoration: underline; }}")?;
+            writeln!(out, "        .broken {{ color: #c0392b; }}")?;
+            writeln!(out, "        .broken a {{ color: #95a5a6; text-decoration: line-through; }}")?;
+            writeln!(out, "    </style>")?;
+            writeln!(out, "</head>")?;
+            writeln!(out, "<body>")?;
+            writeln!(out, "    <h1>{}</h1>", title)?;
+
+            for episode in episodes {
+                writeln!(out, "    <div class=\"episode\">")?;
+                if let Some(cover) = cover(episode) {
+                    writeln!(out, "        <img class=\"cover\" src=\"{}\" alt=\"\">", html_escape(cover))?;
+                }
+                writeln!(out, "        <div class=\"feed-name\">{}</div>", html_escape(&episode.feed_name))?;
+                writeln!(out, "        <div class=\"episode-title\">{}</div>", html_escape(&episode.title))?;
+                writeln!(out, "        <div class=\"date\">{}</div>", date_and_length(episode))?;
+                if let Some(sources) = sources_label(episode) {
+                    writeln!(out, "        <div class=\"sources\">{}</div>", html_escape(&sources))?;
+                }
+                match &episode.broken {
+                    Some(problem) => writeln!(
+                        out,
+                        "        <div class=\"media-link broken\"><a href=\"{}\">Listen</a> broken: {}</div>",
+                        html_escape(&episode.media_url),
+                        html_escape(problem)
+                    )?,
+                    None => writeln!(out, "        <div class=\"media-link\"><a href=\"{}\">Listen</a></div>", html_escape(&episode.media_url))?,
+                }
+                writeln!(out, "    </div>")?;
+            }
+
+            writeln!(out, "</body>")?;
+            writeln!(out, "</html>")?;
+        },
+        "player" => {
+            // A single page with the episodes embedded, so it plays straight from file://
+            let episodes: Vec<Value> = episodes
+                .iter()
+                .map(|e| {
+                    json!({
+                        "id": e.guid,
+                        "feed": e.feed_name,
+                        "title": e.title,
+                        "date": e.pub_date.map(|d| d.to_rfc3339()),
+                        "duration": e.duration,
+                        "url": e.media_url,
+                        "cover": cover(e),
+                        "notes": e.description,
+                        "broken": e.broken,
+                        "sources": e.sources,
+                    })
+                })
+                .collect();
+            // '<' only occurs inside JSON strings, so escaping it keeps </script> and <!-- inert
+            let data = serde_json::to_string(&Value::Array(episodes)).unwrap().replace('<', "\\u003c");
+            let page = PLAYER_TEMPLATE
+                .replace("{{TITLE}}", &html_escape(title))
+                .replace("{{EPISODES}}", &data);
+            out.write_all(page.as_bytes())?;
+        },
+        _ => {
+            // Default plain text format, in the versioned listing format so it can be read back.
+            // Broken episodes get a note after the URL, so players that expect the URL at the
+            // end of the line skip them
+            listing::write_header(out)?;
+            for episode in episodes {
+                listing::write_episode(out, episode)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn has_cached_cover(episode: &Episode) -> bool {
+    episode.cover.as_deref().is_some_and(|cover| Path::new(cover).is_absolute())
+}
+
+// Cached artwork if --covers is on, otherwise the artwork URL
+fn cover(episode: &Episode) -> Option<&str> {
+    episode.cover.as_deref().or(episode.image_url.as_deref())
+}
+
+/// The publication day as `YYYY-MM-DD`, or `Unknown date`.
+pub fn date_label(episode: &Episode) -> String {
+    episode.pub_date
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "Unknown date".to_string())
+}
+
+// Date plus length, for the formats meant to be read rather than parsed
+fn date_and_length(episode: &Episode) -> String {
+    match episode.duration {
+        Some(seconds) => format!("{}, {}", date_label(episode), duration::format(seconds)),
+        None => date_label(episode),
+    }
+}
+
+// The OPML lists an episode's feed is in, when several were given
+fn sources_label(episode: &Episode) -> Option<String> {
+    (!episode.sources.is_empty()).then(|| format!("from {}", episode.sources.join(", ")))
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace("&", "&amp;")
+     .replace("<", "&lt;")
+     .replace(">", "&gt;")
+     .replace("\"", "&quot;")
+     .replace("'", "&#39;")
+}
@@ -0,0 +1,256 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{collections::HashMap, path::Path};
+
+use crate::error::{Error, Result};
+use crate::store::Download;
+
+/// Limits for a set of downloads. Unset limits do not apply.
+#[derive(Clone, Copy, Default)]
+pub struct Policy {
+    /// Keep only the newest N episodes
+    pub last: Option<usize>,
+    /// Keep only episodes published within the last N days
+    pub days: Option<i64>,
+    /// Keep the total size under this many bytes, dropping the oldest episodes first
+    pub max_bytes: Option<u64>,
+}
+
+impl Policy {
+    /// Parse a rule list such as `last=5,days=30,gb=2`.
+    pub fn parse(spec: &str) -> Result<Policy> {
+        let mut policy = Policy::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let invalid = || Error::InvalidArgument(format!("bad retention rule '{}' (expected last=N, days=N or gb=N)", part));
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            match key.trim() {
+                "last" => policy.last = Some(value.trim().parse().map_err(|_| invalid())?),
+                "days" => policy.days = Some(value.trim().parse().map_err(|_| invalid())?),
+                "gb" => policy.max_bytes = Some(parse_gb(value).ok_or_else(invalid)?),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(policy)
+    }
+
+    // Feed rules override the global count and age limits field by field;
+    // the size limit is never inherited, since the global one is for the whole library
+    fn or(self, fallback: Policy) -> Policy {
+        Policy {
+            last: self.last.or(fallback.last),
+            days: self.days.or(fallback.days),
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+/// Convert a (possibly fractional) number of gigabytes to bytes.
+pub fn parse_gb(value: &str) -> Option<u64> {
+    let gb: f64 = value.trim().parse().ok()?;
+    (gb.is_finite() && gb >= 0.0).then_some((gb * 1_000_000_000.0) as u64)
+}
+
+/// Global rules plus per-feed overrides, keyed by feed name or feed URL.
+/// The global `max_bytes` caps the whole library; a feed's `max_bytes` caps that feed.
+#[derive(Default)]
+pub struct Rules {
+    pub global: Policy,
+    pub feeds: HashMap<String, Policy>,
+}
+
+impl Rules {
+    fn for_feed(&self, download: &Download) -> Policy {
+        let feed_rule = self.feeds
+            .get(&download.feed_name)
+            .or_else(|| self.feeds.get(&download.feed_url))
+            .copied()
+            .unwrap_or_default();
+        feed_rule.or(self.global)
+    }
+}
+
+/// A download selected for deletion and the rule that selected it.
+pub struct Expired<'a> {
+    pub download: &'a Download,
+    pub size: u64,
+    pub reason: String,
+}
+
+/// Pick the downloads that fall outside the rules. `downloads` pairs every file
+/// with its size on disk. Kept downloads are never picked, but still count
+/// towards episode counts and size limits.
+pub fn expired<'a>(downloads: &[(&'a Download, u64)], rules: &Rules, now: DateTime<Utc>) -> Vec<Expired<'a>> {
+    let mut by_feed: HashMap<&str, Vec<(&Download, u64)>> = HashMap::new();
+    for &(download, size) in downloads {
+        by_feed.entry(download.feed_url.as_str()).or_default().push((download, size));
+    }
+
+    let mut expired = Vec::new();
+    let mut survivors: Vec<(&Download, u64)> = Vec::new();
+    for (_, mut feed_downloads) in by_feed {
+        feed_downloads.sort_by_key(|(d, _)| std::cmp::Reverse(date_of(d)));
+        let policy = rules.for_feed(feed_downloads[0].0);
+
+        let mut feed_survivors = Vec::new();
+        for (i, (download, size)) in feed_downloads.into_iter().enumerate() {
+            let reason = match policy {
+                _ if download.kept => None,
+                Policy { last: Some(last), .. } if i >= last => Some(format!("beyond the last {} episodes", last)),
+                Policy { days: Some(days), .. } if older_than(date_of(download), days, now) => {
+                    Some(format!("older than {} days", days))
+                }
+                _ => None,
+            };
+            match reason {
+                Some(reason) => expired.push(Expired { download, size, reason }),
+                None => feed_survivors.push((download, size)),
+            }
+        }
+
+        if let Some(max_bytes) = policy.max_bytes {
+            let reason = format!("feed over {}", format_size(max_bytes));
+            feed_survivors = over_quota(feed_survivors, max_bytes, &reason, &mut expired);
+        }
+        survivors.extend(feed_survivors);
+    }
+
+    if let Some(max_bytes) = rules.global.max_bytes {
+        survivors.sort_by_key(|(d, _)| std::cmp::Reverse(date_of(d)));
+        let reason = format!("library over {}", format_size(max_bytes));
+        over_quota(survivors, max_bytes, &reason, &mut expired);
+    }
+    expired
+}
+
+// Walk newest first and drop whatever no longer fits under the quota
+fn over_quota<'a>(
+    downloads: Vec<(&'a Download, u64)>,
+    max_bytes: u64,
+    reason: &str,
+    expired: &mut Vec<Expired<'a>>,
+) -> Vec<(&'a Download, u64)> {
+    // Kept files take their share of the quota first, whatever their age
+    let mut total: u64 = downloads.iter().filter(|(d, _)| d.kept).map(|(_, size)| size).sum();
+    let mut kept = Vec::new();
+    for (download, size) in downloads {
+        if download.kept {
+            kept.push((download, size));
+        } else if total + size <= max_bytes {
+            total += size;
+            kept.push((download, size));
+        } else {
+            expired.push(Expired { download, size, reason: reason.to_string() });
+        }
+    }
+    kept
+}
+
+// Undated episodes are aged by when they were downloaded
+fn date_of(download: &Download) -> DateTime<Utc> {
+    download.pub_date.unwrap_or(download.downloaded_at)
+}
+
+// An age limit reaching back further than dates go keeps everything
+fn older_than(date: DateTime<Utc>, days: i64, now: DateTime<Utc>) -> bool {
+    Duration::try_days(days)
+        .and_then(|age| now.checked_sub_signed(age))
+        .is_some_and(|cutoff| date < cutoff)
+}
+
+/// Remove the folders a deleted file leaves empty (its feed and group folders), up to
+/// but never including the library it was downloaded under.
+pub fn remove_empty_dirs(deleted: &Path, library: &Path) {
+    let mut dir = deleted.parent();
+    while let Some(current) = dir.filter(|d| d.starts_with(library) && *d != library) {
+        // Fails harmlessly on a folder that still holds something
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1_000_000_000 {
+        format!("{:.2} GB", bytes as f64 / 1_000_000_000.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::PathBuf;
+
+    // Episode `day` of a September, in the feed `feed`
+    fn download(feed: &str, day: u32, kept: bool) -> Download {
+        let date = Utc.with_ymd_and_hms(2026, 9, day, 10, 0, 0).unwrap();
+        Download {
+            path: PathBuf::from(format!("{}/{}.mp3", feed, day)),
+            library: None,
+            feed_name: feed.to_string(),
+            feed_url: format!("https://example.com/{}.xml", feed),
+            pub_date: Some(date),
+            downloaded_at: date,
+            kept,
+        }
+    }
+
+    fn deleted(downloads: &[(&Download, u64)], rules: &Rules) -> Vec<String> {
+        let now = Utc.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap();
+        let mut paths: Vec<String> = expired(downloads, rules, now)
+            .iter()
+            .map(|e| format!("{} ({})", e.download.path.display(), e.reason))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn keeps_the_last_episodes_of_each_feed() {
+        let a = [download("a", 1, false), download("a", 2, true), download("a", 3, false), download("a", 4, false)];
+        let b = [download("b", 1, false)];
+        let downloads: Vec<(&Download, u64)> = a.iter().chain(&b).map(|d| (d, 1)).collect();
+        let rules = Rules { global: Policy { last: Some(2), ..Policy::default() }, feeds: HashMap::new() };
+        // The kept episode counts as one of the last two, but is not deleted itself
+        assert_eq!(deleted(&downloads, &rules), ["a/1.mp3 (beyond the last 2 episodes)"]);
+
+        let feeds = HashMap::from([("a".to_string(), Policy { last: Some(3), ..Policy::default() })]);
+        let rules = Rules { global: Policy { last: Some(0), ..Policy::default() }, feeds };
+        assert_eq!(deleted(&downloads, &rules), ["a/1.mp3 (beyond the last 3 episodes)", "b/1.mp3 (beyond the last 0 episodes)"]);
+    }
+
+    #[test]
+    fn quotas_delete_the_oldest_episodes_first() {
+        let a = [download("a", 1, false), download("a", 2, false), download("a", 3, false)];
+        let downloads: Vec<(&Download, u64)> = a.iter().map(|d| (d, 300_000_000)).collect();
+        let rules = Rules { global: Policy { max_bytes: Some(700_000_000), ..Policy::default() }, feeds: HashMap::new() };
+        assert_eq!(deleted(&downloads, &rules), ["a/1.mp3 (library over 700.0 MB)"]);
+
+        // A kept file takes its room first, however old it is
+        let a = [download("a", 1, true), download("a", 2, false), download("a", 3, false)];
+        let downloads: Vec<(&Download, u64)> = a.iter().map(|d| (d, 300_000_000)).collect();
+        assert_eq!(deleted(&downloads, &rules), ["a/2.mp3 (library over 700.0 MB)"]);
+    }
+
+    #[test]
+    fn removes_empty_folders_up_to_the_library() {
+        let library = std::env::temp_dir().join(format!("powercrust-retention-{}", std::process::id()));
+        let feed = library.join("Group").join("Feed");
+        std::fs::create_dir_all(&feed).unwrap();
+        std::fs::write(library.join("Group").join("other.mp3"), b"").unwrap();
+
+        remove_empty_dirs(&feed.join("episode.mp3"), &library);
+        assert!(!feed.exists());
+        assert!(library.join("Group").exists());
+
+        std::fs::remove_file(library.join("Group").join("other.mp3")).unwrap();
+        remove_empty_dirs(&library.join("Group").join("other.mp3"), &library);
+        assert!(!library.join("Group").exists());
+        assert!(library.exists());
+        remove_empty_dirs(&library.join("top.mp3"), &library);
+        assert!(library.exists());
+        std::fs::remove_dir(&library).unwrap();
+    }
+}
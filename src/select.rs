@@ -0,0 +1,315 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+};
+
+use crate::dates::parse_date;
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+use crate::undated::Undated;
+
+/// A publication-date range. Undated episodes fall outside any bounded window.
+#[derive(Clone, Copy, Default)]
+pub struct Window {
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Window {
+    /// Build a window from `--since`/`--until` style options, given as (flag, value) so
+    /// errors can name them. Values are `all` (no bound), a relative age such as `12h`,
+    /// `30d`, `2w`, `6mo` or `1y`, or a date. A bare `until` date includes that whole day.
+    pub fn parse(since: (&str, Option<&str>), until: (&str, Option<&str>), now: DateTime<Utc>) -> Result<Window> {
+        Ok(Window {
+            since: parse_bound(since, now, false)?,
+            until: parse_bound(until, now, true)?,
+        })
+    }
+
+    pub fn contains(&self, episode: &Episode) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        episode.pub_date.is_some_and(|date| {
+            self.since.is_none_or(|since| date >= since) && self.until.is_none_or(|until| date < until)
+        })
+    }
+}
+
+fn parse_bound((flag, value): (&str, Option<&str>), now: DateTime<Utc>, end_of_day: bool) -> Result<Option<DateTime<Utc>>> {
+    let value = match value.map(str::trim) {
+        None => return Ok(None),
+        Some(value) if value.eq_ignore_ascii_case("all") => return Ok(None),
+        Some(value) => value,
+    };
+    // Ages reaching back further than dates go fall through to the error below
+    if let Some(instant) = parse_age(flag, value)?.and_then(|age| now.checked_sub_signed(age)) {
+        return Ok(Some(instant));
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let day = if end_of_day { day.succ_opt().unwrap_or(day) } else { day };
+        return Ok(Some(day.and_hms_opt(0, 0, 0).unwrap().and_utc()));
+    }
+    parse_date(value).map(Some).ok_or_else(|| {
+        Error::InvalidArgument(format!("{} expects a date, an age like 30d or 2w, or 'all', got '{}'", flag, value))
+    })
+}
+
+// "12h", "30d", "2w", "6mo" (30-day months), "1y"; `None` for what is not an age at all.
+// A bare "m" would be minutes elsewhere on the command line, so it is refused rather than guessed
+fn parse_age(flag: &str, value: &str) -> Result<Option<Duration>> {
+    let split = value.find(|c: char| !(c.is_ascii_digit() || c == '-' || c == '+')).unwrap_or(value.len());
+    let Ok(amount) = value[..split].trim().parse::<i64>() else {
+        return Ok(None);
+    };
+    let unit = value[split..].trim().to_ascii_lowercase();
+    let invalid = |reason: &str| Err(Error::InvalidArgument(format!("{} {}, got '{}'", flag, reason, value)));
+    if !matches!(unit.as_str(), "h" | "d" | "w" | "mo" | "y" | "m") {
+        return Ok(None);
+    }
+    if amount < 0 {
+        return invalid("expects an age counted back from now, which cannot be negative");
+    }
+    Ok(match unit.as_str() {
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        "mo" => amount.checked_mul(30).and_then(Duration::try_days),
+        "y" => amount.checked_mul(365).and_then(Duration::try_days),
+        _ => return invalid("takes months as 'mo' (6mo), not 'm'"),
+    })
+}
+
+/// How the newest output picks its episodes.
+pub struct Selection {
+    /// Newest episodes kept per feed; 0 keeps them all
+    pub per_feed: usize,
+    /// Pick this many at random from what the per-feed limit leaves
+    pub sample: Option<usize>,
+    /// Seed for the random sample, so a pick can be repeated
+    pub seed: u64,
+    /// Cap on the whole list, keeping the newest episodes
+    pub max_total: Option<usize>,
+    /// Listening time in seconds to fill, taking the feeds in turn
+    pub budget: Option<u32>,
+}
+
+impl Default for Selection {
+    fn default() -> Selection {
+        Selection { per_feed: 1, sample: None, seed: 0, max_total: None, budget: None }
+    }
+}
+
+/// Pick episodes for the newest output: those inside `window`, the newest `per_feed`
+/// of every feed, then the random sample, the listening budget and the total cap. Undated episodes only
+/// qualify when the window is unbounded and the feed has nothing dated to fill the
+/// per-feed quota. The result is ordered by feed name, newest first within a feed, with
+/// undated episodes placed as `undated` says.
+pub fn newest(episodes: &[Episode], window: &Window, selection: &Selection, undated: Undated) -> Vec<Episode> {
+    let mut by_feed: HashMap<&str, Vec<&Episode>> = HashMap::new();
+    for episode in episodes {
+        if !window.contains(episode) {
+            continue;
+        }
+        by_feed.entry(episode.feed_url.as_str()).or_default().push(episode);
+    }
+
+    let mut picked: Vec<&Episode> = Vec::new();
+    for (_, mut feed_episodes) in by_feed {
+        // Newest first, undated last; stable, so feed order breaks ties
+        feed_episodes.sort_by_key(|e| Reverse(e.pub_date));
+        if selection.per_feed > 0 {
+            feed_episodes.truncate(selection.per_feed);
+        }
+        picked.extend(feed_episodes);
+    }
+
+    if let Some(sample) = selection.sample {
+        // Sort first so the same seed picks the same episodes on every run
+        picked.sort_by(|a, b| (&a.feed_url, &a.guid).cmp(&(&b.feed_url, &b.guid)));
+        shuffle(&mut picked, selection.seed);
+        picked.truncate(sample);
+    }
+
+    if let Some(budget) = selection.budget {
+        picked = fill_budget(picked, budget);
+    }
+
+    if let Some(max_total) = selection.max_total {
+        picked.sort_by_key(|e| Reverse(e.pub_date));
+        picked.truncate(max_total);
+    }
+
+    picked.sort_by(|a, b| a.feed_name.cmp(&b.feed_name).then_with(|| undated.compare_dates(a, b, true)));
+    picked.into_iter().cloned().collect()
+}
+
+// Take the newest episode of each feed in turn, freshest feed first, for as long as
+// episodes fit in what is left of the budget. An episode that does not fit is passed
+// over for the feed's next one; episodes of unknown length cannot be counted and are skipped.
+fn fill_budget(episodes: Vec<&Episode>, budget: u32) -> Vec<&Episode> {
+    let mut by_feed: HashMap<&str, Vec<&Episode>> = HashMap::new();
+    for episode in episodes.into_iter().filter(|e| e.duration.is_some()) {
+        by_feed.entry(episode.feed_url.as_str()).or_default().push(episode);
+    }
+    let mut queues: Vec<VecDeque<&Episode>> = by_feed
+        .into_values()
+        .map(|mut feed_episodes| {
+            feed_episodes.sort_by_key(|e| Reverse(e.pub_date));
+            feed_episodes.into()
+        })
+        .collect();
+    queues.sort_by(|a, b| b[0].pub_date.cmp(&a[0].pub_date).then_with(|| a[0].feed_name.cmp(&b[0].feed_name)));
+
+    let mut left = budget;
+    let mut filled = Vec::new();
+    while queues.iter().any(|queue| !queue.is_empty()) {
+        for queue in &mut queues {
+            if let Some(episode) = queue.pop_front() {
+                let duration = episode.duration.unwrap_or_default();
+                if duration <= left {
+                    left -= duration;
+                    filled.push(episode);
+                }
+            }
+        }
+    }
+    filled
+}
+
+// Fisher-Yates driven by SplitMix64; plenty for picking episodes
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Episode `day` of a September, `minutes` long, in the feed `feed`
+    fn episode(feed: &str, day: u32, minutes: Option<u32>) -> Episode {
+        Episode {
+            feed_name: feed.to_string(),
+            feed_url: format!("https://example.com/{}.xml", feed),
+            group: None,
+            guid: format!("{}-{}", feed, day),
+            title: format!("{} {}", feed, day),
+            pub_date: Some(Utc.with_ymd_and_hms(2026, 9, day, 10, 0, 0).unwrap()),
+            media_url: format!("https://example.com/{}/{}.mp3", feed, day),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: None,
+            duration: minutes.map(|m| m * 60),
+            broken: None,
+            cover: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn guids(episodes: &[Episode]) -> Vec<&str> {
+        episodes.iter().map(|e| e.guid.as_str()).collect()
+    }
+
+    fn pick(episodes: &[Episode], selection: &Selection) -> Vec<Episode> {
+        newest(episodes, &Window::default(), selection, Undated::Keep)
+    }
+
+    #[test]
+    fn keeps_the_newest_per_feed() {
+        let episodes = [episode("b", 1, None), episode("a", 1, None), episode("a", 3, None), episode("a", 2, None)];
+        assert_eq!(guids(&pick(&episodes, &Selection::default())), ["a-3", "b-1"]);
+
+        let two = Selection { per_feed: 2, ..Selection::default() };
+        assert_eq!(guids(&pick(&episodes, &two)), ["a-3", "a-2", "b-1"]);
+
+        let all_capped = Selection { per_feed: 0, max_total: Some(2), ..Selection::default() };
+        assert_eq!(guids(&pick(&episodes, &all_capped)), ["a-3", "a-2"]);
+    }
+
+    #[test]
+    fn samples_repeatably_by_seed() {
+        let episodes: Vec<Episode> = (1..=20).map(|day| episode(&format!("feed{:02}", day), day, None)).collect();
+        let sample = |seed| pick(&episodes, &Selection { sample: Some(5), seed, ..Selection::default() });
+
+        assert_eq!(sample(7).len(), 5);
+        assert_eq!(guids(&sample(7)), guids(&sample(7)));
+        assert_ne!(guids(&sample(7)), guids(&sample(8)));
+        // Input order does not change the pick
+        let reversed: Vec<Episode> = episodes.iter().rev().cloned().collect();
+        let from_reversed = pick(&reversed, &Selection { sample: Some(5), seed: 7, ..Selection::default() });
+        assert_eq!(guids(&from_reversed), guids(&sample(7)));
+    }
+
+    #[test]
+    fn parses_window_bounds() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 1, 12, 0, 0).unwrap();
+        let bound = |since: &str| Window::parse(("--since", Some(since)), ("--until", None), now).map(|w| w.since);
+        let at = |y, m, d, h| Some(Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap());
+
+        assert_eq!(bound("12h").unwrap(), at(2026, 10, 1, 0));
+        assert_eq!(bound("30d").unwrap(), at(2026, 9, 1, 12));
+        assert_eq!(bound("2w").unwrap(), at(2026, 9, 17, 12));
+        assert_eq!(bound("2mo").unwrap(), at(2026, 8, 2, 12));
+        assert_eq!(bound("1y").unwrap(), at(2025, 10, 1, 12));
+        assert_eq!(bound("all").unwrap(), None);
+        assert_eq!(bound("2026-09-01").unwrap(), at(2026, 9, 1, 0));
+        assert_eq!(bound("Tue, 01 Sep 2026 10:00:00 GMT").unwrap(), at(2026, 9, 1, 10));
+        for refused in ["6m", "-30d", "soon", "99999999999999y"] {
+            assert!(matches!(bound(refused), Err(Error::InvalidArgument(_))), "{} should be refused", refused);
+        }
+
+        // A bare date given as the end includes that day
+        let window = Window::parse(("--since", Some("2026-09-01")), ("--until", Some("2026-09-02")), now).unwrap();
+        assert!(window.contains(&episode("a", 2, None)));
+        assert!(!window.contains(&episode("a", 3, None)));
+        assert!(!window.contains(&Episode { pub_date: None, ..episode("a", 2, None) }));
+    }
+
+    #[test]
+    fn fills_the_budget_feed_by_feed() {
+        let episodes = [
+            episode("a", 5, Some(30)),
+            episode("a", 4, Some(90)),
+            episode("a", 3, Some(20)),
+            episode("b", 4, Some(40)),
+            episode("b", 2, None),
+            episode("b", 1, Some(10)),
+        ];
+        let budget = |minutes: u32| pick(&episodes, &Selection { per_feed: 0, budget: Some(minutes * 60), ..Selection::default() });
+
+        // The freshest feed goes first; too long and unknown lengths are passed over for the next episode
+        assert_eq!(guids(&budget(100)), ["a-5", "a-3", "b-4", "b-1"]);
+        assert_eq!(guids(&budget(70)), ["a-5", "b-4"]);
+        assert_eq!(guids(&budget(5)), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn shuffles_into_a_permutation() {
+        let mut items: Vec<u32> = (0..50).collect();
+        shuffle(&mut items, 42);
+        assert_ne!(items, (0..50).collect::<Vec<_>>());
+        let mut sorted = items.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..50).collect::<Vec<_>>());
+
+        let mut again: Vec<u32> = (0..50).collect();
+        shuffle(&mut again, 42);
+        assert_eq!(items, again);
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::Utc;
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::error::{Error, Result};
+use crate::store::{PlaybackChange, Store};
+
+/// Serve the playback state over HTTP until the process is stopped.
+///
+/// `GET /playback` lists every recorded state as JSON. `POST /playback` takes
+/// `{"episode": "<media URL or guid>", "played": true, "position": 754.2, "starred": false}`,
+/// where every field but `episode` is optional, sent as `application/json`. Browser
+/// requests are only answered for `allowed_origins`; pages opened from file:// send the
+/// `null` origin, which has to be listed like any other.
+pub async fn serve(addr: SocketAddr, store: Store, allowed_origins: Vec<String>) -> Result<()> {
+    let store = Arc::new(Mutex::new(store));
+    let origins: Vec<String> = allowed_origins.into_iter().map(|origin| origin.trim_end_matches('/').to_string()).collect();
+    let origins = Arc::new(origins);
+    let make_service = make_service_fn(move |_| {
+        let store = Arc::clone(&store);
+        let origins = Arc::clone(&origins);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let store = Arc::clone(&store);
+                let origins = Arc::clone(&origins);
+                async move { Ok::<_, Infallible>(handle(req, &store, &origins).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|source| Error::Serve { addr: addr.to_string(), source })?
+        .serve(make_service);
+    eprintln!("Listening on http://{}/playback", addr);
+    server.await.map_err(|source| Error::Serve { addr: addr.to_string(), source })
+}
+
+// Any web page can make the browser post here, and CORS headers would only hide the
+// answer, not stop the change; so requests from other origins are refused outright.
+// Requests without an origin come from outside a browser and are let through
+async fn handle(req: Request<Body>, store: &Mutex<Store>, origins: &[String]) -> Response<Body> {
+    let origin = req.headers().get(header::ORIGIN).map(|origin| origin.to_str().unwrap_or_default().to_string());
+    if let Some(origin) = origin.as_ref().filter(|origin| !origins.contains(origin)) {
+        return failure(StatusCode::FORBIDDEN, format!("origin '{}' is not allowed", origin));
+    }
+    let mut response = route(req, store).await;
+    if let Some(origin) = origin {
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.parse().unwrap());
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS".parse().unwrap());
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type".parse().unwrap());
+        headers.insert(header::VARY, "Origin".parse().unwrap());
+    }
+    response
+}
+
+async fn route(req: Request<Body>, store: &Mutex<Store>) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        // CORS preflight for JSON posts
+        (&Method::OPTIONS, _) => respond(StatusCode::NO_CONTENT, Value::Null),
+        (&Method::GET, "/playback") => {
+            let states = store.lock().unwrap().playback();
+            match states {
+                Ok(states) => {
+                    let states: Vec<Value> = states
+                        .into_iter()
+                        .map(|p| {
+                            json!({
+                                "feed_url": p.feed_url,
+                                "guid": p.guid,
+                                "media_url": p.media_url,
+                                "played": p.played,
+                                "position": p.position,
+                                "starred": p.starred,
+                                "updated_at": p.updated_at,
+                            })
+                        })
+                        .collect();
+                    respond(StatusCode::OK, Value::Array(states))
+                }
+                Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        }
+        (&Method::POST, "/playback") => {
+            let content_type = req.headers().get(header::CONTENT_TYPE).map(|v| v.to_str().unwrap_or_default().to_string());
+            let body = match hyper::body::to_bytes(req.into_body()).await {
+                Ok(body) => body,
+                Err(e) => return failure(StatusCode::BAD_REQUEST, e.to_string()),
+            };
+            let (episode, change) = match parse_change(content_type.as_deref(), &body) {
+                Ok(parsed) => parsed,
+                Err(reason) => return failure(StatusCode::BAD_REQUEST, reason),
+            };
+            let updated = store.lock().unwrap().update_playback(&episode, &change, Utc::now());
+            match updated {
+                Ok(0) => failure(StatusCode::NOT_FOUND, format!("unknown episode '{}'", episode)),
+                Ok(updated) => respond(StatusCode::OK, json!({ "updated": updated })),
+                Err(e) => failure(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        }
+        _ => failure(StatusCode::NOT_FOUND, "not found".to_string()),
+    }
+}
+
+// Only JSON is taken: browsers preflight it, so a page cannot post a plain form here
+fn parse_change(content_type: Option<&str>, body: &[u8]) -> std::result::Result<(String, PlaybackChange), String> {
+    let essence = content_type.unwrap_or_default().split(';').next().unwrap_or_default().trim();
+    if !essence.eq_ignore_ascii_case("application/json") {
+        return Err("Content-Type must be application/json".to_string());
+    }
+    let value: Value = serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e))?;
+    let episode = value
+        .get("episode")
+        .and_then(Value::as_str)
+        .ok_or("missing \"episode\" (media URL or guid)")?
+        .to_string();
+    let field = |name: &str| value.get(name).filter(|v| !v.is_null());
+    let flag = |name: &str| {
+        field(name)
+            .map(|v| v.as_bool().ok_or(format!("\"{}\" must be true or false", name)))
+            .transpose()
+    };
+    let change = PlaybackChange {
+        played: flag("played")?,
+        position: field("position")
+            .map(|v| v.as_f64().filter(|p| *p >= 0.0).ok_or("\"position\" must be a number of seconds"))
+            .transpose()?,
+        starred: flag("starred")?,
+    };
+    Ok((episode, change))
+}
+
+fn failure(status: StatusCode, message: String) -> Response<Body> {
+    respond(status, json!({ "error": message }))
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    let body = if body.is_null() { Body::empty() } else { Body::from(body.to_string()) };
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .unwrap()
+}
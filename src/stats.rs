@@ -0,0 +1,176 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+
+use crate::duration;
+use crate::feed::{Episode, Subscription};
+use crate::output::html_escape;
+
+// Average month length, for cadence figures
+const DAYS_PER_MONTH: f64 = 30.44;
+
+/// Publishing summary of one subscription.
+pub struct FeedStats {
+    pub name: String,
+    pub url: String,
+    pub group: Option<String>,
+    pub episodes: usize,
+    pub last_published: Option<DateTime<Utc>>,
+    /// Mean time between consecutive dated episodes
+    pub average_interval: Option<Duration>,
+    /// Dated episodes per month over the last year, or since the first episode if later
+    pub per_month: Option<f64>,
+    /// Median length in seconds of the episodes whose length is known
+    pub median_duration: Option<u32>,
+    /// No episode within the abandonment threshold, or no dated episode at all
+    pub abandoned: bool,
+}
+
+/// Summarize every subscription from its episodes. A feed counts as abandoned when
+/// nothing was published within `abandoned_after` of `now`.
+pub fn feed_stats(feeds: &[Subscription], episodes: &[Episode], now: DateTime<Utc>, abandoned_after: Duration) -> Vec<FeedStats> {
+    feeds
+        .iter()
+        .map(|feed| {
+            let feed_episodes: Vec<&Episode> = episodes.iter().filter(|e| e.feed_url == feed.url).collect();
+            let mut dates: Vec<DateTime<Utc>> = feed_episodes.iter().filter_map(|e| e.pub_date).collect();
+            dates.sort();
+            let mut durations: Vec<u32> = feed_episodes.iter().filter_map(|e| e.duration).collect();
+            durations.sort();
+
+            let last_published = dates.last().copied();
+            let average_interval = match (dates.first(), dates.last()) {
+                (Some(first), Some(last)) if dates.len() > 1 => Some((*last - *first) / (dates.len() as i32 - 1)),
+                _ => None,
+            };
+            let per_month = dates.first().map(|first| {
+                let since = (*first).max(now - Duration::days(365));
+                let months = ((now - since).num_days() as f64 / DAYS_PER_MONTH).max(1.0);
+                dates.iter().filter(|d| **d >= since).count() as f64 / months
+            });
+
+            FeedStats {
+                name: feed.name.clone(),
+                url: feed.url.clone(),
+                group: feed.group.clone(),
+                episodes: feed_episodes.len(),
+                last_published,
+                average_interval,
+                per_month,
+                median_duration: durations.get(durations.len() / 2).copied(),
+                abandoned: last_published.is_none_or(|last| now - last > abandoned_after),
+            }
+        })
+        .collect()
+}
+
+/// Write the report as txt, md, html or json.
+pub fn render<W: Write + ?Sized>(out: &mut W, stats: &[FeedStats], format: &str) -> io::Result<()> {
+    match format {
+        "json" => {
+            let feeds: Vec<Value> = stats
+                .iter()
+                .map(|s| {
+                    json!({
+                        "name": s.name,
+                        "url": s.url,
+                        "group": s.group,
+                        "episodes": s.episodes,
+                        "last_published": s.last_published.map(|d| d.to_rfc3339()),
+                        "average_interval_days": s.average_interval.map(days),
+                        "episodes_per_month": s.per_month,
+                        "median_duration_seconds": s.median_duration,
+                        "abandoned": s.abandoned,
+                    })
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&Value::Array(feeds)).unwrap())
+        }
+        "md" => {
+            writeln!(out, "# Feed Statistics")?;
+            writeln!(out)?;
+            writeln!(out, "| Feed | Episodes | Last published | Average interval | Per month | Median length | Status |")?;
+            writeln!(out, "|------|----------|----------------|------------------|-----------|---------------|--------|")?;
+            for s in stats {
+                let [last, interval, per_month, median, status] = cells(s);
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} | {} |",
+                    s.name.replace('|', "\\|"),
+                    s.episodes,
+                    last,
+                    interval,
+                    per_month,
+                    median,
+                    status
+                )?;
+            }
+            Ok(())
+        }
+        "html" => {
+            writeln!(out, "<!DOCTYPE html>")?;
+            writeln!(out, "<html>")?;
+            writeln!(out, "<head>")?;
+            writeln!(out, "    <meta charset=\"UTF-8\">")?;
+            writeln!(out, "    <title>Feed Statistics</title>")?;
+            writeln!(out, "    <style>")?;
+            writeln!(out, "        body {{ font-family: Arial, sans-serif; margin: 20px; }}")?;
+            writeln!(out, "        h1 {{ color: #333; }}")?;
+            writeln!(out, "        table {{ border-collapse: collapse; }}")?;
+            writeln!(out, "        th, td {{ text-align: left; padding: 6px 12px; border-bottom: 1px solid #eee; }}")?;
+            writeln!(out, "        .abandoned {{ color: #c0392b; }}")?;
+            writeln!(out, "    </style>")?;
+            writeln!(out, "</head>")?;
+            writeln!(out, "<body>")?;
+            writeln!(out, "    <h1>Feed Statistics</h1>")?;
+            writeln!(out, "    <table>")?;
+            writeln!(out, "        <tr><th>Feed</th><th>Episodes</th><th>Last published</th><th>Average interval</th><th>Per month</th><th>Median length</th><th>Status</th></tr>")?;
+            for s in stats {
+                let [last, interval, per_month, median, status] = cells(s);
+                writeln!(
+                    out,
+                    "        <tr{}><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    if s.abandoned { " class=\"abandoned\"" } else { "" },
+                    html_escape(&s.url),
+                    html_escape(&s.name),
+                    s.episodes,
+                    last,
+                    interval,
+                    per_month,
+                    median,
+                    status
+                )?;
+            }
+            writeln!(out, "    </table>")?;
+            writeln!(out, "</body>")?;
+            writeln!(out, "</html>")
+        }
+        _ => {
+            for s in stats {
+                let [last, interval, per_month, median, status] = cells(s);
+                writeln!(
+                    out,
+                    "{}: {} episodes, last {}, every {}, {} per month, median {}, {}",
+                    s.name, s.episodes, last, interval, per_month, median, status
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Last published, average interval, per month, median length and status, as text
+fn cells(s: &FeedStats) -> [String; 5] {
+    let unknown = || "-".to_string();
+    [
+        s.last_published.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_else(unknown),
+        s.average_interval.map(|i| format!("{:.1} days", days(i))).unwrap_or_else(unknown),
+        s.per_month.map(|n| format!("{:.1}", n)).unwrap_or_else(unknown),
+        s.median_duration.map(duration::format).unwrap_or_else(unknown),
+        if s.abandoned { "abandoned" } else { "active" }.to_string(),
+    ]
+}
+
+fn days(interval: Duration) -> f64 {
+    interval.num_seconds() as f64 / 86_400.0
+}
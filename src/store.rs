@@ -0,0 +1,548 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, Row};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+
+// How long to wait for another powercrust process (serve, mark-played, a second run)
+// to finish writing before giving up with "database is locked"
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Schema migrations, applied in order and tracked through `PRAGMA user_version`.
+/// Append new steps; never edit one that has shipped.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        started_at TEXT NOT NULL
+    );
+    CREATE TABLE episodes (
+        feed_url TEXT NOT NULL,
+        guid TEXT NOT NULL,
+        feed_name TEXT NOT NULL,
+        title TEXT NOT NULL,
+        pub_date TEXT,
+        media_url TEXT NOT NULL,
+        first_seen TEXT NOT NULL,
+        last_seen TEXT NOT NULL,
+        PRIMARY KEY (feed_url, guid)
+    );
+    CREATE TABLE sightings (
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        feed_url TEXT NOT NULL,
+        guid TEXT NOT NULL,
+        media_url TEXT NOT NULL,
+        PRIMARY KEY (run_id, feed_url, guid)
+    );
+    CREATE INDEX episodes_pub_date ON episodes(pub_date);",
+    "ALTER TABLE episodes ADD COLUMN enclosure_length INTEGER;
+    ALTER TABLE episodes ADD COLUMN enclosure_type TEXT;",
+    "ALTER TABLE episodes ADD COLUMN feed_group TEXT;",
+    "ALTER TABLE episodes ADD COLUMN description TEXT;
+    ALTER TABLE episodes ADD COLUMN episode_number INTEGER;
+    ALTER TABLE episodes ADD COLUMN image_url TEXT;",
+    "CREATE TABLE downloads (
+        path TEXT PRIMARY KEY,
+        feed_url TEXT NOT NULL,
+        guid TEXT NOT NULL,
+        downloaded_at TEXT NOT NULL,
+        kept INTEGER NOT NULL DEFAULT 0
+    );",
+    "CREATE TABLE playback (
+        feed_url TEXT NOT NULL,
+        guid TEXT NOT NULL,
+        played INTEGER NOT NULL DEFAULT 0,
+        position REAL,
+        starred INTEGER NOT NULL DEFAULT 0,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (feed_url, guid)
+    );",
+    "ALTER TABLE episodes ADD COLUMN duration INTEGER;",
+    "CREATE TABLE link_checks (
+        media_url TEXT PRIMARY KEY,
+        checked_at TEXT NOT NULL,
+        problem TEXT
+    );",
+    "ALTER TABLE downloads ADD COLUMN library TEXT;",
+];
+
+const EPISODE_COLUMNS: &str = "e.feed_name, e.feed_url, e.guid, e.title, e.pub_date, e.media_url, e.enclosure_length, e.enclosure_type, e.feed_group, e.description, e.episode_number, e.image_url, e.duration";
+
+/// A file in the download library, with what retention rules need to know about it.
+pub struct Download {
+    pub path: PathBuf,
+    /// The `--dir` the file was downloaded under; unknown for downloads of older versions
+    pub library: Option<PathBuf>,
+    pub feed_name: String,
+    pub feed_url: String,
+    pub pub_date: Option<DateTime<Utc>>,
+    pub downloaded_at: DateTime<Utc>,
+    /// Marked by the user as never to be pruned
+    pub kept: bool,
+}
+
+/// A change to an episode's playback state; unset fields are left as they are.
+#[derive(Default)]
+pub struct PlaybackChange {
+    pub played: Option<bool>,
+    /// Seconds into the episode
+    pub position: Option<f64>,
+    pub starred: Option<bool>,
+}
+
+/// Playback state of one episode, with the media URL the player knows it by.
+pub struct Playback {
+    pub feed_url: String,
+    pub guid: String,
+    pub media_url: Option<String>,
+    pub played: bool,
+    pub position: Option<f64>,
+    pub starred: bool,
+    pub updated_at: String,
+}
+
+/// Persistent episode history across runs, keyed by feed URL and episode guid.
+pub struct Store {
+    conn: Connection,
+    path: String,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Store> {
+        let conn = Connection::open(path).map_err(|e| Error::store(path.display(), e))?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| Error::store(path.display(), e))?;
+        let mut store = Store { conn, path: path.display().to_string() };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        let version: usize = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| self.error(e))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.transaction().map_err(|e| Error::store(&self.path, e))?;
+            tx.execute_batch(migration)
+                .and_then(|_| tx.pragma_update(None, "user_version", i + 1))
+                .and_then(|_| tx.commit())
+                .map_err(|e| Error::store(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Record the episodes seen in this run and return the new run id.
+    /// New episodes get `first_seen`; known ones have their metadata and `last_seen` refreshed.
+    /// The run stays pending, seen only through this store, until `keep_run`; `discard_run`
+    /// or dropping the store rolls it back. Other processes cannot write to the database
+    /// meanwhile, so keep the run pending no longer than it takes to write the outputs.
+    pub fn record_run(&mut self, started_at: DateTime<Utc>, episodes: &[Episode]) -> Result<i64> {
+        self.conn.execute_batch("BEGIN").map_err(|e| self.error(e))?;
+        insert_run(&mut self.conn, started_at, episodes).map_err(|e| Error::store(&self.path, e))
+    }
+
+    /// Make the pending run permanent, with anything recorded since.
+    pub fn keep_run(&self) -> Result<()> {
+        self.conn.execute_batch("COMMIT").map_err(|e| self.error(e))
+    }
+
+    /// Roll the pending run back, for commands that only read the history.
+    pub fn discard_run(&self) -> Result<()> {
+        self.conn.execute_batch("ROLLBACK").map_err(|e| self.error(e))
+    }
+
+    /// Every known episode of the feeds fetched in `run_id`, oldest first (undated first).
+    pub fn episodes(&self, run_id: i64) -> Result<Vec<Episode>> {
+        let sql = format!(
+            "SELECT {} FROM episodes e
+             WHERE feed_url IN (SELECT feed_url FROM sightings WHERE run_id = ?1)
+             ORDER BY pub_date, feed_name, title",
+            EPISODE_COLUMNS
+        );
+        self.query_episodes(&sql, params![run_id])
+    }
+
+    /// When each episode of the feeds fetched in `run_id` was first seen, by feed URL and guid.
+    pub fn first_seen(&self, run_id: i64) -> Result<HashMap<(String, String), DateTime<Utc>>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT feed_url, guid, first_seen FROM episodes
+                 WHERE feed_url IN (SELECT feed_url FROM sightings WHERE run_id = ?1)",
+            )
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map(params![run_id], |row| Ok(((row.get(0)?, row.get(1)?), row.get::<_, String>(2)?)))
+            .map_err(|e| self.error(e))?;
+        let mut first_seen = HashMap::new();
+        for row in rows {
+            let (key, date) = row.map_err(|e| self.error(e))?;
+            if let Some(date) = parse_date(&date) {
+                first_seen.insert(key, date);
+            }
+        }
+        Ok(first_seen)
+    }
+
+    /// Episodes seen in `run_id` that were not in the previous run which fetched the same feed.
+    /// An episode counts as seen if either its guid or its media URL appeared in that run.
+    /// A feed fetched for the first time only sets the baseline: none of its back catalogue is new.
+    pub fn new_since_previous_run(&self, run_id: i64) -> Result<Vec<Episode>> {
+        let sql = format!(
+            "SELECT {} FROM episodes e
+             JOIN sightings s ON s.feed_url = e.feed_url AND s.guid = e.guid
+             WHERE s.run_id = ?1
+               AND EXISTS (SELECT 1 FROM sightings q WHERE q.feed_url = s.feed_url AND q.run_id < ?1)
+               AND NOT EXISTS (
+                   SELECT 1 FROM sightings p
+                   WHERE p.feed_url = s.feed_url
+                     AND p.run_id = (SELECT MAX(run_id) FROM sightings q
+                                     WHERE q.feed_url = s.feed_url AND q.run_id < ?1)
+                     AND (p.guid = s.guid OR p.media_url = s.media_url)
+               )
+             ORDER BY e.pub_date DESC, e.feed_name",
+            EPISODE_COLUMNS
+        );
+        self.query_episodes(&sql, params![run_id])
+    }
+
+    /// Every recorded run as (id, start time), oldest first.
+    pub fn runs(&self) -> Result<Vec<(i64, DateTime<Utc>)>> {
+        let mut stmt = self.conn.prepare("SELECT id, started_at FROM runs ORDER BY id").map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| self.error(e))?;
+        let mut runs = Vec::new();
+        for row in rows {
+            let (id, started_at) = row.map_err(|e| self.error(e))?;
+            if let Some(started_at) = parse_date(&started_at) {
+                runs.push((id, started_at));
+            }
+        }
+        Ok(runs)
+    }
+
+    /// The episodes the feeds listed in `run_id`, with their current metadata.
+    pub fn run_episodes(&self, run_id: i64) -> Result<Vec<Episode>> {
+        let sql = format!(
+            "SELECT {} FROM episodes e
+             JOIN sightings s ON s.feed_url = e.feed_url AND s.guid = e.guid
+             WHERE s.run_id = ?1",
+            EPISODE_COLUMNS
+        );
+        self.query_episodes(&sql, params![run_id])
+    }
+
+    /// Remember that `episode` was saved to `path`, keeping the original download time.
+    pub fn record_download(&self, episode: &Episode, path: &Path, library: &Path, at: DateTime<Utc>) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO downloads (path, feed_url, guid, downloaded_at, library) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (path) DO UPDATE SET feed_url = excluded.feed_url, guid = excluded.guid, library = excluded.library",
+                params![path.to_string_lossy(), episode.feed_url, episode.guid, format_date(at), library.to_string_lossy()],
+            )
+            .map(|_| ())
+            .map_err(|e| self.error(e))
+    }
+
+    /// Every recorded download, newest episode first.
+    pub fn downloads(&self) -> Result<Vec<Download>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT d.path, COALESCE(e.feed_name, d.feed_url), d.feed_url, e.pub_date, d.downloaded_at, d.kept, d.library
+                 FROM downloads d
+                 LEFT JOIN episodes e ON e.feed_url = d.feed_url AND e.guid = d.guid
+                 ORDER BY COALESCE(e.pub_date, d.downloaded_at) DESC",
+            )
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(Download {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    library: row.get::<_, Option<String>>(6)?.map(PathBuf::from),
+                    feed_name: row.get(1)?,
+                    feed_url: row.get(2)?,
+                    pub_date: row.get::<_, Option<String>>(3)?.and_then(|d| parse_date(&d)),
+                    downloaded_at: parse_date(&row.get::<_, String>(4)?).unwrap_or_default(),
+                    kept: row.get(5)?,
+                })
+            })
+            .map_err(|e| self.error(e))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(|e| self.error(e))
+    }
+
+    /// Feed URL and guid of the episode each recorded download was saved for, by path.
+    pub fn download_owners(&self) -> Result<HashMap<PathBuf, (String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, feed_url, guid FROM downloads")
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((PathBuf::from(row.get::<_, String>(0)?), (row.get(1)?, row.get(2)?))))
+            .map_err(|e| self.error(e))?;
+        rows.collect::<rusqlite::Result<HashMap<_, _>>>().map_err(|e| self.error(e))
+    }
+
+    /// Mark a download as kept (or not). Returns false if the path is not a recorded download.
+    pub fn set_kept(&self, path: &Path, kept: bool) -> Result<bool> {
+        self.conn
+            .execute("UPDATE downloads SET kept = ?2 WHERE path = ?1", params![path.to_string_lossy(), kept])
+            .map(|changed| changed > 0)
+            .map_err(|e| self.error(e))
+    }
+
+    pub fn forget_download(&self, path: &Path) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM downloads WHERE path = ?1", params![path.to_string_lossy()])
+            .map(|_| ())
+            .map_err(|e| self.error(e))
+    }
+
+    /// Apply a playback change to the episode known by `episode`: its guid, its media URL or
+    /// the path it was downloaded to. Marking an episode played clears its position.
+    /// Returns how many episodes matched.
+    pub fn update_playback(&self, episode: &str, change: &PlaybackChange, at: DateTime<Utc>) -> Result<usize> {
+        self.conn
+            .execute(
+                "INSERT INTO playback (feed_url, guid, played, position, starred, updated_at)
+                 SELECT feed_url, guid, COALESCE(?2, 0), ?3, COALESCE(?4, 0), ?5 FROM (
+                     SELECT feed_url, guid FROM episodes WHERE guid = ?1 OR media_url = ?1
+                     UNION
+                     SELECT feed_url, guid FROM downloads WHERE path = ?1
+                 ) WHERE true
+                 ON CONFLICT (feed_url, guid) DO UPDATE SET
+                     played = COALESCE(?2, played),
+                     position = CASE WHEN ?2 THEN NULL ELSE COALESCE(?3, position) END,
+                     starred = COALESCE(?4, starred),
+                     updated_at = ?5",
+                params![episode, change.played, change.position, change.starred, format_date(at)],
+            )
+            .map_err(|e| self.error(e))
+    }
+
+    /// Feed URL and guid of every episode marked played.
+    pub fn played_episodes(&self) -> Result<HashSet<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT feed_url, guid FROM playback WHERE played")
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| self.error(e))?;
+        rows.collect::<rusqlite::Result<HashSet<_>>>().map_err(|e| self.error(e))
+    }
+
+    /// Every recorded playback state, most recently changed first.
+    pub fn playback(&self) -> Result<Vec<Playback>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT p.feed_url, p.guid, e.media_url, p.played, p.position, p.starred, p.updated_at
+                 FROM playback p
+                 LEFT JOIN episodes e ON e.feed_url = p.feed_url AND e.guid = p.guid
+                 ORDER BY p.updated_at DESC",
+            )
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(Playback {
+                    feed_url: row.get(0)?,
+                    guid: row.get(1)?,
+                    media_url: row.get(2)?,
+                    played: row.get(3)?,
+                    position: row.get(4)?,
+                    starred: row.get(5)?,
+                    updated_at: row.get(6)?,
+                })
+            })
+            .map_err(|e| self.error(e))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(|e| self.error(e))
+    }
+
+    /// Remember the outcome of checking a media URL; `problem` is `None` when it works.
+    pub fn record_link_check(&self, media_url: &str, problem: Option<&str>, at: DateTime<Utc>) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO link_checks (media_url, checked_at, problem) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (media_url) DO UPDATE SET checked_at = excluded.checked_at, problem = excluded.problem",
+                params![media_url, format_date(at), problem],
+            )
+            .map(|_| ())
+            .map_err(|e| self.error(e))
+    }
+
+    /// Media URLs whose last check found them broken, with the reason.
+    pub fn broken_links(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT media_url, problem FROM link_checks WHERE problem IS NOT NULL")
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| self.error(e))?;
+        rows.collect::<rusqlite::Result<HashMap<_, _>>>().map_err(|e| self.error(e))
+    }
+
+    fn query_episodes(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Episode>> {
+        let mut stmt = self.conn.prepare(sql).map_err(|e| self.error(e))?;
+        let rows = stmt.query_map(params, episode_from_row).map_err(|e| self.error(e))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: rusqlite::Error) -> Error {
+        Error::store(&self.path, e)
+    }
+}
+
+fn insert_run(conn: &mut Connection, started_at: DateTime<Utc>, episodes: &[Episode]) -> rusqlite::Result<i64> {
+    let now = format_date(started_at);
+    let tx = conn.savepoint()?;
+    tx.execute("INSERT INTO runs (started_at) VALUES (?1)", params![now])?;
+    let run_id = tx.last_insert_rowid();
+    {
+        let mut upsert = tx.prepare(
+            "INSERT INTO episodes (feed_url, guid, feed_name, title, pub_date, media_url,
+                                   enclosure_length, enclosure_type, feed_group, description,
+                                   episode_number, image_url, duration, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?14)
+             ON CONFLICT (feed_url, guid) DO UPDATE SET
+                 feed_name = excluded.feed_name,
+                 title = excluded.title,
+                 pub_date = excluded.pub_date,
+                 media_url = excluded.media_url,
+                 enclosure_length = excluded.enclosure_length,
+                 enclosure_type = excluded.enclosure_type,
+                 feed_group = excluded.feed_group,
+                 description = excluded.description,
+                 episode_number = excluded.episode_number,
+                 image_url = excluded.image_url,
+                 duration = excluded.duration,
+                 last_seen = excluded.last_seen",
+        )?;
+        let mut sighting = tx.prepare(
+            "INSERT OR IGNORE INTO sightings (run_id, feed_url, guid, media_url) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for episode in episodes {
+            upsert.execute(params![
+                episode.feed_url,
+                episode.guid,
+                episode.feed_name,
+                episode.title,
+                episode.pub_date.map(format_date),
+                episode.media_url,
+                episode.enclosure_length,
+                episode.enclosure_type,
+                episode.group,
+                episode.description,
+                episode.episode_number,
+                episode.image_url,
+                episode.duration,
+                now,
+            ])?;
+            sighting.execute(params![run_id, episode.feed_url, episode.guid, episode.media_url])?;
+        }
+    }
+    tx.commit()?;
+    Ok(run_id)
+}
+
+fn episode_from_row(row: &Row) -> rusqlite::Result<Episode> {
+    Ok(Episode {
+        feed_name: row.get(0)?,
+        feed_url: row.get(1)?,
+        group: row.get(8)?,
+        guid: row.get(2)?,
+        title: row.get(3)?,
+        pub_date: row.get::<_, Option<String>>(4)?.and_then(|d| parse_date(&d)),
+        media_url: row.get(5)?,
+        enclosure_length: row.get(6)?,
+        enclosure_type: row.get(7)?,
+        description: row.get(9)?,
+        episode_number: row.get(10)?,
+        image_url: row.get(11)?,
+        duration: row.get(12)?,
+        broken: None,
+        cover: None,
+        sources: Vec::new(),
+    })
+}
+
+// Dates are stored as UTC RFC 3339 with a fixed width, so they sort as text
+fn format_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s).ok().map(|d| d.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn episode(feed: &str, guid: &str, url: &str) -> Episode {
+        Episode {
+            feed_name: feed.to_string(),
+            feed_url: format!("https://example.com/{}.xml", feed),
+            group: None,
+            guid: guid.to_string(),
+            title: guid.to_string(),
+            pub_date: Some(Utc.with_ymd_and_hms(2026, 9, 1, 10, 0, 0).unwrap()),
+            media_url: format!("https://example.com/{}.mp3", url),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: None,
+            duration: None,
+            broken: None,
+            cover: None,
+            sources: Vec::new(),
+        }
+    }
+
+    // Records and keeps a run of `episodes`, returning the guids new since the previous run
+    fn run(store: &mut Store, day: u32, episodes: &[Episode]) -> Vec<String> {
+        let run_id = store.record_run(Utc.with_ymd_and_hms(2026, 10, day, 6, 0, 0).unwrap(), episodes).unwrap();
+        let new = store.new_since_previous_run(run_id).unwrap();
+        store.keep_run().unwrap();
+        let mut guids: Vec<String> = new.into_iter().map(|e| e.guid).collect();
+        guids.sort();
+        guids
+    }
+
+    #[test]
+    fn reports_episodes_new_since_the_feed_was_last_fetched() {
+        let mut store = Store::open(Path::new(":memory:")).unwrap();
+        let a1 = episode("a", "a1", "a1");
+        let a2 = episode("a", "a2", "a2");
+        let b1 = episode("b", "b1", "b1");
+
+        // A feed's first fetch is only the baseline
+        assert!(run(&mut store, 1, std::slice::from_ref(&a1)).is_empty());
+        assert_eq!(run(&mut store, 2, &[a1.clone(), a2.clone(), b1.clone()]), ["a2"]);
+        // A run without feed b does not reset what b was last seen with
+        assert!(run(&mut store, 3, &[a1.clone(), a2.clone()]).is_empty());
+        let b2 = episode("b", "b2", "b2");
+        // Re-published with a new guid or a new URL is not new
+        let a2_moved = episode("a", "a2", "a2-moved");
+        let a1_reguid = episode("a", "a1-again", "a1");
+        assert_eq!(run(&mut store, 4, &[a1_reguid, a2_moved, b1, b2]), ["b2"]);
+    }
+
+    #[test]
+    fn discarded_runs_leave_no_trace() {
+        let mut store = Store::open(Path::new(":memory:")).unwrap();
+        run(&mut store, 1, &[episode("a", "a1", "a1")]);
+        store.record_run(Utc::now(), &[episode("a", "a2", "a2")]).unwrap();
+        store.discard_run().unwrap();
+        assert_eq!(run(&mut store, 2, &[episode("a", "a2", "a2")]), ["a2"]);
+        assert_eq!(store.runs().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,75 @@
+use id3::{frame, Tag, TagLike, Timestamp, Version};
+use chrono::{Datelike, Timelike};
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::feed::Episode;
+use crate::mp4;
+
+/// Cover art fetched for an episode.
+pub struct Cover {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Write the episode's feed metadata into a downloaded file: ID3v2.4 for MP3,
+/// iTunes atoms for MP4/M4A. `extension` decides the container, since the file
+/// may still carry its `.part` name. Other formats are left alone.
+pub fn write_tags(path: &Path, extension: &str, episode: &Episode, cover: Option<&Cover>) -> Result<()> {
+    let tag_error = |reason: String| Error::Tag { path: path.display().to_string(), reason };
+    match extension.to_ascii_lowercase().as_str() {
+        "mp3" => write_id3(path, episode, cover).map_err(|e| tag_error(e.to_string())),
+        "m4a" | "m4b" | "mp4" | "m4v" | "mov" => {
+            let metadata = mp4::Metadata {
+                title: &episode.title,
+                album: &episode.feed_name,
+                date: episode.pub_date.map(|d| d.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+                track: episode.episode_number,
+                comment: episode.description.as_deref(),
+                cover: cover.map(|c| (c.data.as_slice(), c.mime_type.as_str())),
+            };
+            mp4::write_metadata(path, &metadata).map_err(|e| tag_error(e.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn write_id3(path: &Path, episode: &Episode, cover: Option<&Cover>) -> id3::Result<()> {
+    // Start over if the existing tag is missing or unreadable junk
+    let mut tag = Tag::read_from_path(path).unwrap_or_default();
+
+    tag.set_title(episode.title.as_str());
+    tag.set_album(episode.feed_name.as_str());
+    if let Some(date) = episode.pub_date {
+        tag.set_date_recorded(Timestamp {
+            year: date.year(),
+            month: Some(date.month() as u8),
+            day: Some(date.day() as u8),
+            hour: Some(date.hour() as u8),
+            minute: Some(date.minute() as u8),
+            second: Some(date.second() as u8),
+        });
+    }
+    if let Some(number) = episode.episode_number {
+        tag.set_track(number);
+    }
+    if let Some(description) = &episode.description {
+        tag.remove_comment(None, None);
+        tag.add_frame(frame::Comment {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: description.clone(),
+        });
+    }
+    if let Some(cover) = cover {
+        tag.remove_all_pictures();
+        tag.add_frame(frame::Picture {
+            mime_type: cover.mime_type.clone(),
+            picture_type: frame::PictureType::CoverFront,
+            description: String::new(),
+            data: cover.data.clone(),
+        });
+    }
+
+    tag.write_to_path(path, Version::Id3v24)
+}
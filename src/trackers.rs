@@ -0,0 +1,170 @@
+use futures::{stream, StreamExt};
+use reqwest::{header, Client, StatusCode};
+use std::collections::HashMap;
+
+use crate::error::{Error, Result};
+
+/// Analytics redirects that wrap the real media URL as a path, written as rule patterns:
+/// the URL without scheme up to where the wrapped URL starts, with `*` standing for
+/// one path segment or part of one (an id, a file extension, a subdomain).
+const BUILTIN_RULES: &[&str] = &[
+    "dts.podtrac.com/redirect.*/",
+    "*.podtrac.com/pts/redirect.*/",
+    "podtrac.com/pts/redirect.*/",
+    "chrt.fm/track/*/",
+    "pdst.fm/e/",
+    "pscrb.fm/rss/p/",
+    "verifi.podscribe.com/rss/p/",
+    "mgln.ai/e/*/",
+    "prfx.byspotify.com/e/",
+    "op3.dev/e/",
+    "op3.dev/e,*/",
+    "arttrk.com/p/*/",
+    "claritaspod.com/measure/",
+    "pfx.vpixl.com/*/",
+];
+
+/// Prefix rules for unwrapping tracking redirects from media URLs.
+pub struct TrackerRules {
+    patterns: Vec<String>,
+}
+
+impl TrackerRules {
+    /// The built-in rules.
+    pub fn builtin() -> TrackerRules {
+        TrackerRules { patterns: BUILTIN_RULES.iter().map(|p| p.to_string()).collect() }
+    }
+
+    /// Add a rule such as `track.example.com/r/*/`; a missing trailing slash is added.
+    pub fn add(&mut self, pattern: &str) -> Result<()> {
+        let pattern = pattern.trim();
+        let pattern = pattern.split_once("://").map_or(pattern, |(_, rest)| rest);
+        let host = pattern.split('/').next().unwrap_or_default();
+        if !pattern.contains('/') || !host.contains('.') {
+            return Err(Error::InvalidArgument(format!(
+                "--tracker-rule expects a host and path prefix like track.example.com/r/*/, got '{}'",
+                pattern
+            )));
+        }
+        let pattern = if pattern.ends_with('/') { pattern.to_string() } else { format!("{}/", pattern) };
+        self.patterns.push(pattern);
+        Ok(())
+    }
+
+    /// The URL with every tracking redirect unwrapped, or `None` if no rule applies.
+    /// The wrapped URL keeps its own scheme if it has one, and takes the outer one otherwise.
+    pub fn strip(&self, url: &str) -> Option<String> {
+        let url = url.trim();
+        let (scheme, mut rest) = url.split_once("://").unwrap_or(("https", url));
+        let mut scheme = scheme.to_string();
+        let mut stripped = false;
+        while let Some(len) = self.patterns.iter().find_map(|p| match_prefix(p.as_bytes(), rest.as_bytes())) {
+            let mut inner = &rest[len..];
+            for candidate in ["https:", "http:"] {
+                if inner.get(..candidate.len()).is_some_and(|s| s.eq_ignore_ascii_case(candidate)) {
+                    scheme = candidate.trim_end_matches(':').to_string();
+                    inner = inner[candidate.len()..].trim_start_matches('/');
+                }
+            }
+            // A wrapped URL needs at least a host
+            if !inner.split('/').next().is_some_and(|host| host.contains('.') || host.contains(':')) {
+                break;
+            }
+            rest = inner;
+            stripped = true;
+        }
+        stripped.then(|| format!("{}://{}", scheme, rest))
+    }
+}
+
+// Length of `url` matched by `pattern`, ignoring ASCII case. `*` matches one or more
+// characters other than '/', as few as let the rest of the pattern match.
+fn match_prefix(pattern: &[u8], url: &[u8]) -> Option<usize> {
+    match pattern.split_first() {
+        None => Some(0),
+        Some((b'*', rest)) => (1..=url.len())
+            .take_while(|&n| url[n - 1] != b'/')
+            .find_map(|n| match_prefix(rest, &url[n..]).map(|len| n + len)),
+        Some((c, rest)) => {
+            let (u, url_rest) = url.split_first()?;
+            u.eq_ignore_ascii_case(c).then(|| match_prefix(rest, url_rest).map(|len| len + 1)).flatten()
+        }
+    }
+}
+
+/// Unwrap the tracking redirects of `urls`, returning the URLs that changed. With
+/// `verify`, each unwrapped URL is kept only if a HEAD request (or a one-byte GET, for
+/// servers that refuse HEAD) succeeds; the others are reported and left wrapped.
+pub async fn unwrap(client: &Client, rules: &TrackerRules, urls: Vec<String>, verify: bool) -> HashMap<String, String> {
+    let candidates: Vec<(String, String)> = urls
+        .into_iter()
+        .filter_map(|url| rules.strip(&url).map(|stripped| (url, stripped)))
+        .collect();
+    if !verify {
+        return candidates.into_iter().collect();
+    }
+
+    stream::iter(candidates)
+        .map(|(url, stripped)| async move {
+            let reachable = reachable(client, &stripped).await;
+            if !reachable {
+                eprintln!("Warning: {} did not answer, keeping the tracked URL", stripped);
+            }
+            reachable.then_some((url, stripped))
+        })
+        .buffer_unordered(8)
+        .filter_map(|pair| async move { pair })
+        .collect()
+        .await
+}
+
+async fn reachable(client: &Client, url: &str) -> bool {
+    match client.head(url).send().await {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) if matches!(response.status(), StatusCode::METHOD_NOT_ALLOWED | StatusCode::FORBIDDEN) => client
+            .get(url)
+            .header(header::RANGE, "bytes=0-0")
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwraps_nested_redirects() {
+        let rules = TrackerRules::builtin();
+        let strip = |url: &str| rules.strip(url);
+
+        assert_eq!(strip("https://dts.podtrac.com/redirect.mp3/cdn.example.com/1.mp3").as_deref(), Some("https://cdn.example.com/1.mp3"));
+        assert_eq!(
+            strip("https://chrt.fm/track/ABC123/pdst.fm/e/dts.podtrac.com/redirect.mp3/http://cdn.example.com/1.mp3?x=1").as_deref(),
+            Some("http://cdn.example.com/1.mp3?x=1")
+        );
+        // The outer scheme carries over when the wrapped URL has none
+        assert_eq!(strip("http://op3.dev/e,pg=abc/cdn.example.com/1.mp3").as_deref(), Some("http://cdn.example.com/1.mp3"));
+        assert_eq!(strip("https://WWW.PODTRAC.COM/PTS/REDIRECT.MP3/cdn.example.com/1.mp3").as_deref(), Some("https://cdn.example.com/1.mp3"));
+        assert_eq!(strip("https://cdn.example.com/1.mp3"), None);
+        // A prefix with nothing like a host behind it is left alone
+        assert_eq!(strip("https://pdst.fm/e/episode"), None);
+    }
+
+    #[test]
+    fn stars_match_within_one_segment() {
+        let mut rules = TrackerRules { patterns: Vec::new() };
+        rules.add("https://track.example.com/r/*").unwrap();
+        assert_eq!(rules.strip("https://track.example.com/r/id-42/cdn.example.com/1.mp3").as_deref(), Some("https://cdn.example.com/1.mp3"));
+        // `*` does not reach over a slash, and "b" is no host to unwrap to
+        assert_eq!(rules.strip("https://track.example.com/r/a/b/cdn.example.com/1.mp3"), None);
+        assert_eq!(match_prefix(b"a*/", b"abc/d"), Some(4));
+        assert_eq!(match_prefix(b"a*/", b"a/d"), None);
+        assert_eq!(match_prefix(b"*.example.com/", b"x.y.example.com/z"), Some(16));
+
+        assert!(rules.add("no-path.example.com").is_err());
+        assert!(rules.add("localhost/r/").is_err());
+    }
+}
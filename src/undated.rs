@@ -0,0 +1,257 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::error::{Error, Result};
+use crate::feed::{Episode, FetchedFeed};
+
+/// What to do with episodes that have no publication date.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Undated {
+    /// Keep them, ordered as the oldest episodes
+    Keep,
+    /// Leave them out of every output
+    Drop,
+    /// Keep them at the start of every list
+    First,
+    /// Keep them at the end of every list
+    Last,
+}
+
+impl Undated {
+    pub fn parse(value: &str) -> Result<Undated> {
+        match value {
+            "keep" => Ok(Undated::Keep),
+            "drop" => Ok(Undated::Drop),
+            "first" => Ok(Undated::First),
+            "last" => Ok(Undated::Last),
+            _ => Err(Error::InvalidArgument(format!("--undated expects keep, drop, first or last, got '{}'", value))),
+        }
+    }
+
+    /// Drop undated episodes if the policy says so.
+    pub fn retain(self, episodes: &mut Vec<Episode>) {
+        if self == Undated::Drop {
+            episodes.retain(|episode| episode.pub_date.is_some());
+        }
+    }
+
+    /// Sort by publication date, placing undated episodes according to the policy.
+    /// Ties are broken by feed name and title so every code path orders alike.
+    pub fn sort(self, episodes: &mut [Episode], newest_first: bool) {
+        episodes.sort_by(|a, b| {
+            self.compare_dates(a, b, newest_first)
+                .then_with(|| a.feed_name.cmp(&b.feed_name))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+    }
+
+    /// Order two episodes by publication date alone, placing undated ones by the policy.
+    pub fn compare_dates(self, a: &Episode, b: &Episode, newest_first: bool) -> Ordering {
+        match (a.pub_date, b.pub_date) {
+            (Some(a_date), Some(b_date)) if newest_first => b_date.cmp(&a_date),
+            (Some(a_date), Some(b_date)) => a_date.cmp(&b_date),
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => self.undated_order(newest_first),
+            (Some(_), None) => self.undated_order(newest_first).reverse(),
+        }
+    }
+
+    // Where an undated episode goes relative to a dated one
+    fn undated_order(self, newest_first: bool) -> Ordering {
+        match self {
+            Undated::First => Ordering::Less,
+            Undated::Last => Ordering::Greater,
+            // Undated episodes count as the oldest
+            Undated::Keep | Undated::Drop if newest_first => Ordering::Greater,
+            Undated::Keep | Undated::Drop => Ordering::Less,
+        }
+    }
+}
+
+/// Where a missing publication date may be inferred from, tried in the order given.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DateSource {
+    /// The Last-Modified header of the feed response
+    LastModified,
+    /// The dates of the neighbouring items in the feed
+    FeedOrder,
+    /// When the episode database first saw the episode
+    FirstSeen,
+}
+
+impl DateSource {
+    pub fn parse_list(values: &str) -> Result<Vec<DateSource>> {
+        values
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|value| match value {
+                "last-modified" => Ok(DateSource::LastModified),
+                "feed-order" => Ok(DateSource::FeedOrder),
+                "first-seen" => Ok(DateSource::FirstSeen),
+                _ => Err(Error::InvalidArgument(format!(
+                    "--infer-dates expects last-modified, feed-order or first-seen, got '{}'",
+                    value
+                ))),
+            })
+            .collect()
+    }
+}
+
+/// Episodes are identified by feed URL and guid.
+pub type EpisodeKey = (String, String);
+
+/// Work out a date for every undated episode that one of the sources can vouch for.
+/// `episodes` must be in feed order, as parsed; `first_seen` comes from the episode database.
+pub fn infer_dates(
+    sources: &[DateSource],
+    feeds: &[FetchedFeed],
+    episodes: &[Episode],
+    first_seen: &HashMap<EpisodeKey, DateTime<Utc>>,
+) -> HashMap<EpisodeKey, DateTime<Utc>> {
+    let mut inferred = HashMap::new();
+    for source in sources {
+        let found = match source {
+            DateSource::LastModified => from_last_modified(feeds, episodes),
+            DateSource::FeedOrder => from_feed_order(episodes),
+            DateSource::FirstSeen => first_seen.clone(),
+        };
+        for (key, date) in found {
+            inferred.entry(key).or_insert(date);
+        }
+    }
+    inferred
+}
+
+/// Fill in inferred dates for episodes that have none.
+pub fn apply(episodes: &mut [Episode], inferred: &HashMap<EpisodeKey, DateTime<Utc>>) {
+    for episode in episodes.iter_mut().filter(|e| e.pub_date.is_none()) {
+        episode.pub_date = inferred.get(&(episode.feed_url.clone(), episode.guid.clone())).copied();
+    }
+}
+
+fn from_last_modified(feeds: &[FetchedFeed], episodes: &[Episode]) -> HashMap<EpisodeKey, DateTime<Utc>> {
+    let last_modified: HashMap<&str, DateTime<Utc>> = feeds
+        .iter()
+        .filter_map(|feed| Some((feed.subscription.url.as_str(), feed.last_modified?)))
+        .collect();
+    episodes
+        .iter()
+        .filter(|e| e.pub_date.is_none())
+        .filter_map(|e| Some((key(e), *last_modified.get(e.feed_url.as_str())?)))
+        .collect()
+}
+
+// Feeds list items newest first, so an undated item is placed just after the
+// dated item below it, or just before the one above it at the end of the feed
+fn from_feed_order(episodes: &[Episode]) -> HashMap<EpisodeKey, DateTime<Utc>> {
+    let mut by_feed: HashMap<&str, Vec<&Episode>> = HashMap::new();
+    for episode in episodes {
+        by_feed.entry(episode.feed_url.as_str()).or_default().push(episode);
+    }
+
+    let mut inferred = HashMap::new();
+    for feed_episodes in by_feed.values() {
+        // Nearest dated item above each item, then below it, with the distance to it
+        let mut newer = Vec::with_capacity(feed_episodes.len());
+        let mut last = None;
+        for episode in feed_episodes {
+            newer.push(last);
+            last = match (episode.pub_date, last) {
+                (Some(date), _) => Some((0, date)),
+                (None, Some((n, date))) => Some((n + 1, date)),
+                (None, None) => None,
+            };
+        }
+        let mut last = None;
+        for (i, episode) in feed_episodes.iter().enumerate().rev() {
+            if episode.pub_date.is_some() {
+                last = episode.pub_date.map(|date| (0, date));
+                continue;
+            }
+            let date = match (last, newer[i]) {
+                (Some((n, date)), _) => date + Duration::seconds(n as i64 + 1),
+                (None, Some((n, date))) => date - Duration::seconds(n as i64 + 1),
+                (None, None) => continue,
+            };
+            inferred.insert(key(episode), date);
+            last = last.map(|(n, date)| (n + 1, date));
+        }
+    }
+    inferred
+}
+
+fn key(episode: &Episode) -> EpisodeKey {
+    (episode.feed_url.clone(), episode.guid.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Episode `title` of the feed "a", published on `day` of September if given
+    fn episode(title: &str, day: Option<u32>) -> Episode {
+        Episode {
+            feed_name: "a".to_string(),
+            feed_url: "https://example.com/a.xml".to_string(),
+            group: None,
+            guid: title.to_string(),
+            title: title.to_string(),
+            pub_date: day.map(|d| Utc.with_ymd_and_hms(2026, 9, d, 10, 0, 0).unwrap()),
+            media_url: format!("https://example.com/{}.mp3", title),
+            enclosure_length: None,
+            enclosure_type: None,
+            description: None,
+            episode_number: None,
+            image_url: None,
+            duration: None,
+            broken: None,
+            cover: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn sorted(policy: &str, newest_first: bool) -> Vec<String> {
+        let mut episodes = vec![episode("old", Some(1)), episode("undated", None), episode("new", Some(3))];
+        let policy = Undated::parse(policy).unwrap();
+        policy.retain(&mut episodes);
+        policy.sort(&mut episodes, newest_first);
+        episodes.into_iter().map(|e| e.title).collect()
+    }
+
+    #[test]
+    fn places_undated_episodes_by_policy() {
+        assert_eq!(sorted("keep", true), ["new", "old", "undated"]);
+        assert_eq!(sorted("keep", false), ["undated", "old", "new"]);
+        assert_eq!(sorted("drop", true), ["new", "old"]);
+        assert_eq!(sorted("drop", false), ["old", "new"]);
+        assert_eq!(sorted("first", true), ["undated", "new", "old"]);
+        assert_eq!(sorted("first", false), ["undated", "old", "new"]);
+        assert_eq!(sorted("last", true), ["new", "old", "undated"]);
+        assert_eq!(sorted("last", false), ["old", "new", "undated"]);
+        assert!(Undated::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn infers_dates_from_feed_order() {
+        // As the feed lists them, newest first
+        let episodes = [
+            episode("top", None),
+            episode("newest", Some(20)),
+            episode("middle 1", None),
+            episode("middle 2", None),
+            episode("oldest", Some(10)),
+            episode("bottom", None),
+        ];
+        let inferred = infer_dates(&[DateSource::FeedOrder], &[], &episodes, &HashMap::new());
+        let date = |title: &str| inferred.get(&("https://example.com/a.xml".to_string(), title.to_string())).copied();
+        let at = |day, seconds| Some(Utc.with_ymd_and_hms(2026, 9, day, 10, 0, seconds).unwrap());
+
+        assert_eq!(date("middle 2"), at(10, 1));
+        assert_eq!(date("middle 1"), at(10, 2));
+        assert_eq!(date("top"), at(20, 1));
+        assert_eq!(date("bottom"), Some(at(10, 0).unwrap() - Duration::seconds(1)));
+        assert_eq!(date("newest"), None);
+    }
+}